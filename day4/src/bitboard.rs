@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use crate::{get_cell_offset, Direction, Letter, LetterMatrix, ReadDirection};
+use crate::word_search::{word_patterns, Pattern, WordMatch};
+
+// Word search on bitplanes, for very wide grids.
+//
+// Every letter that occurs in a searched word gets one bitset per row, with
+// bit x set if that letter is in column x. A word read along (dx, dy) starts
+// at every cell where the bitset of its first letter, AND the bitset of its
+// i-th letter i rows further down shifted by i * dx columns, are all set.
+// That's a handful of word-sized shifts and ANDs per row, instead of a step
+// per letter and direction.
+
+#[derive(Clone)]
+struct Bitset {
+    blocks: Vec<u64>,
+}
+
+impl Bitset {
+    fn new(width: usize) -> Bitset {
+        Bitset { blocks: vec![0; width.div_ceil(64)] }
+    }
+
+    fn set(&mut self, bit: usize) {
+        self.blocks[bit / 64] |= 1 << (bit % 64);
+    }
+
+    // Moves bit x + amount to x
+    fn shifted_down(&self, amount: usize) -> Bitset {
+        let (block_shift, bit_shift) = (amount / 64, amount % 64);
+        let mut shifted = Bitset { blocks: vec![0; self.blocks.len()] };
+        for index in 0..self.blocks.len() {
+            let source = index + block_shift;
+            let Some(&low) = self.blocks.get(source) else { break };
+            let high = self.blocks.get(source + 1).copied().unwrap_or(0);
+            shifted.blocks[index] = if bit_shift == 0 { low } else { (low >> bit_shift) | (high << (64 - bit_shift)) };
+        }
+        shifted
+    }
+
+    // Moves bit x - amount to x
+    fn shifted_up(&self, amount: usize) -> Bitset {
+        let (block_shift, bit_shift) = (amount / 64, amount % 64);
+        let mut shifted = Bitset { blocks: vec![0; self.blocks.len()] };
+        for index in block_shift..self.blocks.len() {
+            let high = self.blocks[index - block_shift];
+            let low = if index > block_shift { self.blocks[index - block_shift - 1] } else { 0 };
+            shifted.blocks[index] = if bit_shift == 0 { high } else { (high << bit_shift) | (low >> (64 - bit_shift)) };
+        }
+        shifted
+    }
+
+    fn and_assign(&mut self, other: &Bitset) {
+        self.blocks.iter_mut().zip(&other.blocks).for_each(|(block, other)| *block &= other);
+    }
+
+    fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.blocks.iter().enumerate().flat_map(|(index, &block)| {
+            (0..64).filter(move |bit| block & (1 << bit) != 0).map(move |bit| index * 64 + bit)
+        })
+    }
+}
+
+pub struct BitboardSearch<L: Letter> {
+    patterns: Vec<Pattern<L>>,
+}
+
+impl<L: Letter> BitboardSearch<L> {
+    pub fn new(words: &[&str]) -> BitboardSearch<L> {
+        BitboardSearch { patterns: word_patterns(words) }
+    }
+
+    fn bitplanes(&self, matrix: &LetterMatrix<L>) -> HashMap<L, Vec<Bitset>> {
+        let mut planes: HashMap<L, Vec<Bitset>> = HashMap::new();
+        for pattern in &self.patterns {
+            for &letter in &pattern.letters {
+                planes.entry(letter).or_insert_with(|| vec![Bitset::new(matrix.width); matrix.height()]);
+            }
+        }
+
+        for y in 0..matrix.height() {
+            for x in 0..matrix.width {
+                if let Some(plane) = planes.get_mut(&matrix.letter_at(x, y)) {
+                    plane[y].set(x);
+                }
+            }
+        }
+
+        planes
+    }
+
+    fn find_starts(planes: &HashMap<L, Vec<Bitset>>, letters: &[L], direction: Direction, y: usize) -> Bitset {
+        let (dx, dy) = get_cell_offset(&direction);
+        let mut starts = planes[&letters[0]][y].clone();
+        for (step, letter) in letters.iter().enumerate().skip(1) {
+            let row = &planes[letter][y + step * dy as usize];
+            match dx {
+                1 => starts.and_assign(&row.shifted_down(step)),
+                -1 => starts.and_assign(&row.shifted_up(step)),
+                _ => starts.and_assign(row),
+            }
+        }
+        starts
+    }
+
+    pub fn find_all(&self, matrix: &LetterMatrix<L>) -> Vec<WordMatch> {
+        let planes = self.bitplanes(matrix);
+        let mut matches = Vec::new();
+
+        for direction in [Direction::Horizontal, Direction::Vertical, Direction::Diagonal, Direction::AntiDiagonal] {
+            let (dx, dy) = get_cell_offset(&direction);
+
+            for pattern in &self.patterns {
+                let length = pattern.letters.len();
+
+                // Single letters occupy the same cell in every direction,
+                // so they are only reported once, in the rows
+                if length == 1 && direction != Direction::Horizontal {
+                    continue;
+                }
+
+                let reach = (length - 1) * dy as usize;
+                if reach >= matrix.height() {
+                    continue;
+                }
+
+                for y in 0..matrix.height() - reach {
+                    for x in Self::find_starts(&planes, &pattern.letters, direction, y).ones() {
+                        for &(word, read_direction) in &pattern.origins {
+                            let (x, y) = match read_direction {
+                                ReadDirection::Forward => (x, y),
+                                ReadDirection::Backward => (
+                                    x.wrapping_add_signed(dx * (length as isize - 1)),
+                                    y + reach,
+                                ),
+                            };
+                            matches.push(WordMatch { word, x, y, direction, read_direction, length });
+                        }
+                    }
+                }
+            }
+        }
+
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{input, word_search::WordSearch};
+
+    // Both backends find the same words on an input some 8000 columns wide,
+    // made of each row of the input repeated side by side
+    #[test]
+    fn agrees_with_automaton() {
+        let widened: String = input().lines().map(|line| line.repeat(60) + "\n").collect();
+        let widened_matrix = LetterMatrix::new(&widened).unwrap();
+        let automaton_matches: HashSet<WordMatch> = WordSearch::new(&["XMAS", "MASS"]).find_all(&widened_matrix).into_iter().collect();
+        let bitboard_matches: HashSet<WordMatch> = BitboardSearch::new(&["XMAS", "MASS"]).find_all(&widened_matrix).into_iter().collect();
+        assert_eq!(automaton_matches, bitboard_matches);
+    }
+}
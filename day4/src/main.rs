@@ -1,9 +1,30 @@
-use std::collections::HashSet;
+use std::{borrow::Cow, cmp::min, collections::HashSet, fmt, hash::Hash, ops::Range};
+
+use bitboard::BitboardSearch;
+use render::{render, RenderStyle};
+use template::{Orientations, Template, TemplateMatcher};
+use word_search::{WordMatch, WordSearch};
+
+// The extensions are only exercised by the unit tests, leaving some of
+// them unused by the puzzle answers
+#[cfg_attr(not(test), allow(dead_code))]
+mod bitboard;
+#[cfg_attr(not(test), allow(dead_code))]
+mod render;
+#[cfg_attr(not(test), allow(dead_code))]
+mod template;
+#[cfg_attr(not(test), allow(dead_code))]
+mod word_search;
 
 fn input() -> &'static str {
     include_str!("../inputs/1.txt")
 }
 
+#[cfg(test)]
+fn sample_input() -> &'static str {
+    include_str!("../inputs/sample.txt")
+}
+
 // Simplest & fastest idea: given each 4x4 cell in the input matrix,
 // check if it contains the XMAS or SAMX pattern in any direction
 // manually, byte-by-byte.
@@ -15,6 +36,7 @@ fn input() -> &'static str {
 // rows, columns, diagonals, etc. without copying the input data.
 // Then, use these iterators to check for XMAS/SAMX occurrences.
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 enum Direction {
     Horizontal,
     Vertical,
@@ -22,253 +44,500 @@ enum Direction {
     AntiDiagonal
 }
 
-struct LetterMatrix<'a> {
-    letters: &'a [u8],
+// A single cell of the matrix. Bytes are the fast path for ASCII grids,
+// chars allow any other script at the cost of decoding the input into an
+// owned copy.
+trait Letter: Copy + Eq + Hash + fmt::Debug {
+    const NEWLINE: Self;
+
+    fn decode(text: &str) -> Vec<Self>;
+    fn to_char(self) -> char;
+}
+
+impl Letter for u8 {
+    const NEWLINE: u8 = b'\n';
+
+    fn decode(text: &str) -> Vec<u8> {
+        text.bytes().collect()
+    }
+
+    fn to_char(self) -> char {
+        self as char
+    }
+}
+
+impl Letter for char {
+    const NEWLINE: char = '\n';
+
+    fn decode(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    fn to_char(self) -> char {
+        self
+    }
+}
+
+// The iterators rely on every row being exactly `width` letters followed
+// by a single '\n', so input in any other layout is normalised into an
+// owned copy on construction. Well-formed input is still borrowed as is.
+struct LetterMatrix<'a, L: Letter = u8> {
+    letters: Cow<'a, [L]>,
     width: usize
 }
 
-struct LinearIterator<'a> {
-    matrix: &'a LetterMatrix<'a>,
+#[derive(PartialEq, Eq, Debug)]
+enum MatrixError {
+    Empty,
+    RaggedRow { row: usize, expected_width: usize, width: usize },
+    NonAscii { row: usize, column: usize },
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixError::Empty => write!(f, "letter matrix is empty"),
+            MatrixError::RaggedRow { row, expected_width, width } => write!(
+                f,
+                "row {} has {} letters, expected {} like the first non-empty row",
+                row + 1, width, expected_width
+            ),
+            MatrixError::NonAscii { row, column } => write!(
+                f,
+                "row {} column {} is not ASCII, use a Unicode letter matrix instead",
+                row + 1, column + 1
+            ),
+        }
+    }
+}
+
+// A rectangular part of the matrix, in cells
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Area {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Region<'a, L: Letter = u8> {
+    matrix: &'a LetterMatrix<'a, L>,
+    area: Area,
+}
+
+// Yields the letters of consecutive lines of a region, separated by a
+// synthetic '\n'. Both ends are tracked as (line, offset) cursors: the
+// front cursor points at the next letter to yield, the back cursor just
+// past it, and an offset at the end of a line stands for its separator.
+struct LinearIterator<'a, L: Letter = u8> {
+    matrix: &'a LetterMatrix<'a, L>,
+    area: Area,
     direction: Direction,
-    cursor: usize,
-    did_wrap: bool
+    front: (usize, usize),
+    back: (usize, usize),
+    remaining: usize,
+}
+
+// Splits the input into rows of equal width, measured in letters
+fn split_rows<L: Letter>(letters: &str) -> Result<(Vec<&str>, usize), MatrixError> {
+    let rows: Vec<&str> = letters.trim_end_matches(['\r', '\n']).lines().collect();
+    let row_width = |row: &str| L::decode(row).len();
+
+    // Blank rows before the first letters are ragged rather than making
+    // the whole matrix empty
+    let width = rows.iter().map(|row| row_width(row)).find(|&width| width > 0).unwrap_or(0);
+    if width == 0 {
+        return Err(MatrixError::Empty);
+    }
+
+    if let Some((row, line)) = rows.iter().enumerate().find(|(_, line)| row_width(line) != width) {
+        return Err(MatrixError::RaggedRow { row, expected_width: width, width: row_width(line) });
+    }
+
+    Ok((rows, width))
 }
 
 impl<'a> LetterMatrix<'a> {
-    fn rows(&'a self) -> LinearIterator<'a> {
-        LinearIterator {
-            matrix: self,
-            direction: Direction::Horizontal,
-            cursor: 0,
-            did_wrap: false
+    fn new(letters: &'a str) -> Result<LetterMatrix<'a>, MatrixError> {
+        for (row, line) in letters.lines().enumerate() {
+            if let Some(column) = line.chars().position(|character| !character.is_ascii()) {
+                return Err(MatrixError::NonAscii { row, column });
+            }
         }
+
+        let (rows, width) = split_rows::<u8>(letters)?;
+        let trimmed = letters.trim_end_matches(['\r', '\n']);
+        let is_normalised = letters.len() == trimmed.len() + 1
+            && letters.ends_with('\n')
+            && !trimmed.contains('\r');
+
+        let letters = if is_normalised {
+            Cow::Borrowed(letters.as_bytes())
+        } else {
+            let mut normalised = rows.join("\n");
+            normalised.push('\n');
+            Cow::Owned(normalised.into_bytes())
+        };
+
+        Ok(LetterMatrix { letters, width })
     }
+}
 
-    fn columns(&'a self) -> LinearIterator<'a> {
-        LinearIterator {
-            matrix: self,
-            direction: Direction::Vertical,
-            cursor: 0,
-            did_wrap: false
+impl LetterMatrix<'static, char> {
+    fn new_unicode(letters: &str) -> Result<LetterMatrix<'static, char>, MatrixError> {
+        let (rows, width) = split_rows::<char>(letters)?;
+
+        let mut decoded = Vec::with_capacity(rows.len() * (width + 1));
+        for row in rows {
+            decoded.extend(row.chars());
+            decoded.push('\n');
         }
+
+        Ok(LetterMatrix { letters: Cow::Owned(decoded), width })
     }
+}
 
-    fn diagonals(&'a self) -> LinearIterator<'a> {
-        LinearIterator {
-            matrix: self,
-            direction: Direction::Diagonal,
-            cursor: 0,
-            did_wrap: false
-        }
+// Some of these shortcuts are only used by the unit tests
+#[cfg_attr(not(test), allow(dead_code))]
+impl<'a, L: Letter> LetterMatrix<'a, L> {
+    fn height(&self) -> usize {
+        self.letters.len() / (self.width + 1)
     }
 
-    fn anti_diagonals(&'a self) -> LinearIterator<'a> {
-        LinearIterator {
-            matrix: self,
-            direction: Direction::AntiDiagonal,
-            cursor: 0,
-            did_wrap: false
-        }
+    fn letter_at(&self, x: usize, y: usize) -> L {
+        self.letters[y * (self.width + 1) + x]
+    }
+
+    fn position_of(&self, index: usize) -> (usize, usize) {
+        (index % (self.width + 1), index / (self.width + 1))
+    }
+
+    fn region(&'a self, x: usize, y: usize, width: usize, height: usize) -> Option<Region<'a, L>> {
+        let fits = width > 0 && height > 0 && x + width <= self.width && y + height <= self.height();
+        fits.then_some(Region { matrix: self, area: Area { x, y, width, height } })
+    }
+
+    fn whole(&'a self) -> Region<'a, L> {
+        Region { matrix: self, area: Area { x: 0, y: 0, width: self.width, height: self.height() } }
+    }
+
+    fn rows(&'a self) -> LinearIterator<'a, L> {
+        self.whole().rows()
+    }
+
+    fn columns(&'a self) -> LinearIterator<'a, L> {
+        self.whole().columns()
+    }
+
+    fn diagonals(&'a self) -> LinearIterator<'a, L> {
+        self.whole().diagonals()
+    }
+
+    fn anti_diagonals(&'a self) -> LinearIterator<'a, L> {
+        self.whole().anti_diagonals()
     }
 }
 
-fn get_step(matrix: &LetterMatrix, direction: &Direction) -> usize {
-    match direction {
-        Direction::Horizontal => 1,
-        Direction::Vertical => matrix.width + 1,
-        Direction::Diagonal => matrix.width + 2,
-        Direction::AntiDiagonal => matrix.width
+impl<'a, L: Letter> Region<'a, L> {
+    fn rows(self) -> LinearIterator<'a, L> {
+        self.lines(Direction::Horizontal)
+    }
+
+    fn columns(self) -> LinearIterator<'a, L> {
+        self.lines(Direction::Vertical)
+    }
+
+    fn diagonals(self) -> LinearIterator<'a, L> {
+        self.lines(Direction::Diagonal)
+    }
+
+    fn anti_diagonals(self) -> LinearIterator<'a, L> {
+        self.lines(Direction::AntiDiagonal)
+    }
+
+    fn lines(self, direction: Direction) -> LinearIterator<'a, L> {
+        LinearIterator::new(self, direction, 0..get_line_count(&self.area, &direction))
+    }
+
+    // A single row, column or diagonal. Diagonals are numbered starting at
+    // the bottom left corner, anti-diagonals starting at the top left one.
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn line(self, direction: Direction, index: usize) -> Option<LinearIterator<'a, L>> {
+        (index < get_line_count(&self.area, &direction)).then(|| LinearIterator::new(self, direction, index..index + 1))
     }
 }
 
-fn get_wrapping_step(direction: &Direction) -> usize {
+fn get_cell_offset(direction: &Direction) -> (isize, isize) {
     match direction {
-        Direction::Horizontal => 0,
-        Direction::Vertical => 1,
-        Direction::Diagonal => 0,
-        Direction::AntiDiagonal => 0
+        Direction::Horizontal => (1, 0),
+        Direction::Vertical => (0, 1),
+        Direction::Diagonal => (1, 1),
+        Direction::AntiDiagonal => (-1, 1)
     }
 }
 
-fn get_newline_step(matrix: &LetterMatrix, direction: &Direction) -> usize {
+fn get_line_count(area: &Area, direction: &Direction) -> usize {
     match direction {
-        Direction::Horizontal => 1,
-        Direction::Vertical => matrix.letters.len() - matrix.width,
-        Direction::Diagonal => 1,
-        Direction::AntiDiagonal => matrix.letters.len() - 1
+        Direction::Horizontal => area.height,
+        Direction::Vertical => area.width,
+        Direction::Diagonal | Direction::AntiDiagonal => area.width + area.height - 1
     }
 }
 
-fn advance_cursor(matrix: &LetterMatrix, cursor: usize, direction: &Direction) -> (usize, bool) {
-    let step = get_step(matrix, direction);
-    let moved = cursor + step;
-    let mut moved_wrapped = moved % matrix.letters.len();
-    let mut did_wrap = moved >= matrix.letters.len();
+// The first cell of a line relative to the area, and the line's length
+fn get_line_start(area: &Area, direction: &Direction, line: usize) -> ((usize, usize), usize) {
+    let start = match direction {
+        Direction::Horizontal => (0, line),
+        Direction::Vertical => (line, 0),
+        Direction::Diagonal if line < area.height => (0, area.height - 1 - line),
+        Direction::Diagonal => (line + 1 - area.height, 0),
+        Direction::AntiDiagonal if line < area.width => (line, 0),
+        Direction::AntiDiagonal => (area.width - 1, line + 1 - area.width),
+    };
+    let length = match direction {
+        Direction::Horizontal => area.width,
+        Direction::Vertical => area.height,
+        Direction::Diagonal => min(area.width - start.0, area.height - start.1),
+        Direction::AntiDiagonal => min(start.0 + 1, area.height - start.1),
+    };
+    (start, length)
+}
+
+impl<'a, L: Letter> LinearIterator<'a, L> {
+    fn new(region: Region<'a, L>, direction: Direction, lines: Range<usize>) -> LinearIterator<'a, L> {
+        let letter_count: usize = lines.clone().map(|line| get_line_start(&region.area, &direction, line).1).sum();
+        let last_line = lines.end - 1;
 
-    if did_wrap {
-        moved_wrapped = (moved_wrapped + get_wrapping_step(direction)) % matrix.letters.len();
+        LinearIterator {
+            matrix: region.matrix,
+            area: region.area,
+            direction,
+            front: (lines.start, 0),
+            back: (last_line, get_line_start(&region.area, &direction, last_line).1),
+            remaining: letter_count + lines.len() - 1,
+        }
     }
 
-    if matrix.letters[moved_wrapped] == b'\n' {
-        moved_wrapped = (moved_wrapped + get_newline_step(matrix, direction)) % matrix.letters.len();
-        did_wrap = true;
+    fn line_length(&self, line: usize) -> usize {
+        get_line_start(&self.area, &self.direction, line).1
     }
 
-    (moved_wrapped, did_wrap)
+    fn letter_in_line(&self, line: usize, offset: usize) -> (usize, L) {
+        let ((start_x, start_y), _) = get_line_start(&self.area, &self.direction, line);
+        let (dx, dy) = get_cell_offset(&self.direction);
+        let x = (self.area.x + start_x).wrapping_add_signed(dx * offset as isize);
+        let y = (self.area.y + start_y).wrapping_add_signed(dy * offset as isize);
+        let index = y * (self.matrix.width + 1) + x;
+        (index, self.matrix.letters[index])
+    }
 }
 
-impl Iterator for LinearIterator<'_> {
-    type Item = (usize, u8);
+impl<L: Letter> Iterator for LinearIterator<'_, L> {
+    type Item = (usize, L);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.did_wrap && self.cursor == 0 {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let (line, offset) = self.front;
+        if offset < self.line_length(line) {
+            self.front.1 += 1;
+            Some(self.letter_in_line(line, offset))
+        } else {
+            self.front = (line + 1, 0);
+            Some((0, L::NEWLINE))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<L: Letter> DoubleEndedIterator for LinearIterator<'_, L> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
             return None;
         }
+        self.remaining -= 1;
 
-        if self.did_wrap {
-            self.did_wrap = false;
-            Some((0, b'\n'))
+        let (line, offset) = self.back;
+        if offset > 0 {
+            self.back.1 -= 1;
+            Some(self.letter_in_line(line, offset - 1))
         } else {
-            let prev_cursor = self.cursor;
-            (self.cursor, self.did_wrap) = advance_cursor(self.matrix, self.cursor, &self.direction);
-            Some((prev_cursor, self.matrix.letters[prev_cursor]))
+            self.back = (line - 1, self.line_length(line - 1));
+            Some((0, L::NEWLINE))
         }
     }
 }
 
+impl<L: Letter> ExactSizeIterator for LinearIterator<'_, L> {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 enum ReadDirection {
     Forward,
     Backward,
 }
 
-fn find_xmas_occurences(letters: LinearIterator) -> usize {
-    let mut xmas_count = 0;
-
-    let mut last_char = b'\0';
-    let mut direction: Option<ReadDirection> = None;
-    for (_, char) in letters {
-        match char {
-            b'X' => {
-                if matches!(direction, Some(ReadDirection::Backward)) && last_char == b'M' {
-                    xmas_count += 1;
-                }
-                direction = Some(ReadDirection::Forward);
-            },
-            b'M' => {
-                match direction {
-                    Some(ReadDirection::Forward) => {
-                        if last_char != b'X' {
-                            direction = None;
-                        }
-                    },
-                    Some(ReadDirection::Backward) => {
-                        if last_char != b'A' {
-                            direction = None;
-                        }
-                    },
-                    _ => {}
-                }
-            },
-            b'A' => {
-                match direction {
-                    Some(ReadDirection::Forward) => {
-                        if last_char != b'M' {
-                            direction = None;
-                        }
-                    },
-                    Some(ReadDirection::Backward) => {
-                        if last_char != b'S' {
-                            direction = None;
-                        }
-                    },
-                    _ => {}
-                }
-            },
-            b'S' => {
-                if matches!(direction, Some(ReadDirection::Forward)) && last_char == b'A' {
-                    xmas_count += 1;
-                }
-                direction = Some(ReadDirection::Backward);
-            },
-            _ => {
-                direction = None;
-            }
-        }
-        last_char = char;
+// The automaton walks each line letter by letter, the bitboard search
+// processes 64 columns at a time and pays off on very wide grids
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(not(test), allow(dead_code))]
+enum SearchBackend {
+    Automaton,
+    Bitboard,
+}
+
+fn find_words<L: Letter>(matrix: &LetterMatrix<L>, words: &[&str], backend: SearchBackend) -> Vec<WordMatch> {
+    match backend {
+        SearchBackend::Automaton => WordSearch::new(words).find_all(matrix),
+        SearchBackend::Bitboard => BitboardSearch::new(words).find_all(matrix),
     }
+}
 
-    xmas_count
+#[cfg_attr(not(test), allow(dead_code))]
+fn render_found_words<L: Letter>(matrix: &LetterMatrix<L>, words: &[&str], backend: SearchBackend, style: RenderStyle) -> String {
+    let highlighted: HashSet<(usize, usize)> = find_words(matrix, words, backend)
+        .iter()
+        .flat_map(|word_match| word_match.cells())
+        .collect();
+    render(matrix, &highlighted, style)
 }
 
-fn day4_1() -> usize {
-    let matrix = LetterMatrix {
-        letters: input().as_bytes(),
-        width: input().find('\n').unwrap(),
-    };
-    let xmas_count_in_rows = find_xmas_occurences(matrix.rows());
-    let xmas_count_in_columns = find_xmas_occurences(matrix.columns());
-    let xmas_count_in_diagonals = find_xmas_occurences(matrix.diagonals());
-    let xmas_count_in_anti_diagonals = find_xmas_occurences(matrix.anti_diagonals());
-    xmas_count_in_rows + xmas_count_in_columns + xmas_count_in_diagonals + xmas_count_in_anti_diagonals
-}
-
-fn find_mas_centers(letters: LinearIterator) -> HashSet<usize> {
-    let mut mas_centers = HashSet::new();
-
-    let mut last_char = b'\0';
-    let mut direction: Option<ReadDirection> = None;
-    let mut last_a: usize = 0;
-    for (index, char) in letters {
-        match char {
-            b'M' => {
-                if matches!(direction, Some(ReadDirection::Backward)) && last_char == b'A' {
-                    mas_centers.insert(last_a);
-                }
-                direction = Some(ReadDirection::Forward);
-            },
-            b'A' => {
-                match direction {
-                    Some(ReadDirection::Forward) => {
-                        if last_char != b'M' {
-                            direction = None;
-                        }
-                    },
-                    Some(ReadDirection::Backward) => {
-                        if last_char != b'S' {
-                            direction = None;
-                        }
-                    },
-                    _ => {}
-                }
-                last_a = index;
-            },
-            b'S' => {
-                if matches!(direction, Some(ReadDirection::Forward)) && last_char == b'A' {
-                    mas_centers.insert(last_a);
-                }
-                direction = Some(ReadDirection::Backward);
-            },
-            _ => {
-                direction = None;
-            }
-        }
-        last_char = char;
+// ASCII grids take the byte fast path, anything else is decoded into chars
+fn count_words(letters: &str, words: &[&str], backend: SearchBackend) -> usize {
+    if letters.is_ascii() {
+        find_words(&LetterMatrix::new(letters).unwrap(), words, backend).len()
+    } else {
+        find_words(&LetterMatrix::new_unicode(letters).unwrap(), words, backend).len()
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn render_words(letters: &str, words: &[&str], backend: SearchBackend, style: RenderStyle) -> String {
+    if letters.is_ascii() {
+        render_found_words(&LetterMatrix::new(letters).unwrap(), words, backend, style)
+    } else {
+        render_found_words(&LetterMatrix::new_unicode(letters).unwrap(), words, backend, style)
     }
+}
+
+fn day4_1(backend: SearchBackend) -> usize {
+    count_words(input(), &["XMAS"], backend)
+}
 
-    mas_centers
+const X_MAS: &str = "\
+M.S
+.A.
+M.S
+";
+
+fn count_template_matches(letters: &str, template: &str, orientations: Orientations) -> usize {
+    TemplateMatcher::new(&Template::parse(template), orientations)
+        .find_all(&LetterMatrix::new(letters).unwrap())
+        .len()
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn render_template_matches(letters: &str, template: &str, orientations: Orientations, style: RenderStyle) -> String {
+    let matrix = LetterMatrix::new(letters).unwrap();
+    let matcher = TemplateMatcher::new(&Template::parse(template), orientations);
+    let highlighted: HashSet<(usize, usize)> = matcher
+        .find_all(&matrix)
+        .iter()
+        .flat_map(|template_match| matcher.cells(template_match))
+        .collect();
+    render(&matrix, &highlighted, style)
 }
 
 fn day4_2() -> usize {
-    let matrix = LetterMatrix {
-        letters: input().as_bytes(),
-        width: input().find('\n').unwrap(),
-    };
-    let mas_centers_in_diagonals = find_mas_centers(matrix.diagonals());
-    let mas_centers_in_anti_diagonals = find_mas_centers(matrix.anti_diagonals());
-    mas_centers_in_diagonals.intersection(&mas_centers_in_anti_diagonals).count()
+    count_template_matches(input(), X_MAS, Orientations::All)
 }
 
 fn main() {
-    assert_eq!(day4_1(), 2517);
+    assert_eq!(day4_1(SearchBackend::Automaton), 2517);
     assert_eq!(day4_2(), 1960);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect_letters(letters: impl Iterator<Item = (usize, u8)>) -> String {
+        letters.map(|(_, letter)| letter as char).collect()
+    }
+
+    #[test]
+    fn lines() {
+        let matrix = LetterMatrix::new("ABC\nDEF\nGHI\n").unwrap();
+        assert_eq!(collect_letters(matrix.rows()), "ABC\nDEF\nGHI");
+        assert_eq!(collect_letters(matrix.columns().rev()), "IFC\nHEB\nGDA");
+        assert_eq!(collect_letters(matrix.diagonals()), "G\nDH\nAEI\nBF\nC");
+        assert_eq!(collect_letters(matrix.anti_diagonals()), "A\nBD\nCEG\nFH\nI");
+        assert_eq!(matrix.diagonals().len(), 13);
+        assert_eq!(collect_letters(matrix.whole().line(Direction::Diagonal, 2).unwrap().rev()), "IEA");
+        assert!(matrix.whole().line(Direction::Vertical, 3).is_none());
+        // Both ends meet in the middle without yielding anything twice
+        let mut anti_diagonals = matrix.anti_diagonals();
+        assert_eq!(anti_diagonals.next_back(), Some((10, b'I')));
+        assert_eq!(anti_diagonals.next_back(), Some((0, b'\n')));
+        assert_eq!(anti_diagonals.next(), Some((0, b'A')));
+        assert_eq!(anti_diagonals.len(), 10);
+        assert_eq!(collect_letters(anti_diagonals.by_ref().rev()), "HF\nGEC\nDB\n");
+        assert_eq!(anti_diagonals.next(), None);
+    }
+
+    #[test]
+    fn regions() {
+        let matrix = LetterMatrix::new("ABC\nDEF\nGHI\n").unwrap();
+        let region = matrix.region(1, 1, 2, 2).unwrap();
+        assert_eq!(collect_letters(region.columns()), "EH\nFI");
+        assert_eq!(collect_letters(region.anti_diagonals()), "E\nFH\nI");
+        assert!(matrix.region(2, 0, 2, 1).is_none());
+    }
+
+    // Every layout of the same grid reads the same
+    #[test]
+    fn layouts() {
+        for layout in ["XMAS\nSAMX\n", "XMAS\r\nSAMX\r\n", "XMAS\nSAMX", "XMAS\r\nSAMX", "XMAS\nSAMX\n\n\n"] {
+            let matrix = LetterMatrix::new(layout).unwrap();
+            assert_eq!(matrix.letters.as_ref(), b"XMAS\nSAMX\n");
+            assert_eq!(count_words(layout, &["XMAS"], SearchBackend::Automaton), 2);
+        }
+        assert!(matches!(LetterMatrix::new("XMAS\nSAMX\n").unwrap().letters, Cow::Borrowed(_)));
+        // Rows are measured in chars, not bytes
+        assert_eq!(LetterMatrix::new_unicode("XMAS\r\nSÄMX").unwrap().letters.as_ref(), &['X', 'M', 'A', 'S', '\n', 'S', 'Ä', 'M', 'X', '\n']);
+    }
+
+    #[test]
+    fn malformed_grids() {
+        assert_eq!(LetterMatrix::new("").err(), Some(MatrixError::Empty));
+        assert_eq!(LetterMatrix::new("\r\n\n").err(), Some(MatrixError::Empty));
+        assert_eq!(LetterMatrix::new("XMAS\nSAM\nXMAS\n").err(), Some(MatrixError::RaggedRow { row: 1, expected_width: 4, width: 3 }));
+        assert_eq!(LetterMatrix::new("XMAS\n\nXMAS\n").err(), Some(MatrixError::RaggedRow { row: 1, expected_width: 4, width: 0 }));
+        assert_eq!(LetterMatrix::new("\nXMAS\n").err(), Some(MatrixError::RaggedRow { row: 0, expected_width: 4, width: 0 }));
+        assert_eq!(LetterMatrix::new("\n\n").err(), Some(MatrixError::Empty));
+        assert_eq!(MatrixError::RaggedRow { row: 1, expected_width: 4, width: 3 }.to_string(), "row 2 has 3 letters, expected 4 like the first non-empty row");
+        assert_eq!(LetterMatrix::new("XMAS\nSÄMX\n").err(), Some(MatrixError::NonAscii { row: 1, column: 1 }));
+        assert_eq!(LetterMatrix::new_unicode("XMAS\nSÄM\n").err(), Some(MatrixError::RaggedRow { row: 1, expected_width: 4, width: 3 }));
+    }
+
+    #[test]
+    fn word_counts() {
+        for backend in [SearchBackend::Automaton, SearchBackend::Bitboard] {
+            assert_eq!(count_words(sample_input(), &["XMAS"], backend), 18);
+            // Overlapping words, a word that contains another, and palindromes
+            // (which must not be counted once per reading direction)
+            assert_eq!(count_words("ABABA\nXXXXX\n", &["ABA"], backend), 2);
+            assert_eq!(count_words("ABABA\nXXXXX\n", &["ABA", "BAB", "A"], backend), 6);
+            assert_eq!(count_words("XMASAMX\n", &["XMAS", "SAMX"], backend), 4);
+            // Multi-byte letters keep the grid geometry intact
+            assert_eq!(count_words("ÄBÇ\nДÉЖ\n語ЖÄ\n", &["ÄÉÄ", "ЖÉД", "語"], backend), 3);
+            assert_eq!(day4_1(backend), 2517);
+        }
+    }
+}
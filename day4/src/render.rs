@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use crate::{Letter, LetterMatrix};
+
+// Renders the letter matrix with only the highlighted cells readable, like
+// the illustrations in the puzzle text. Useful to check what a search
+// actually found rather than just how much.
+
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderStyle {
+    // Other letters are replaced by '.'
+    Dotted,
+    // Other letters are dimmed with ANSI escape codes
+    Dimmed,
+}
+
+pub fn render<L: Letter>(matrix: &LetterMatrix<L>, highlighted: &HashSet<(usize, usize)>, style: RenderStyle) -> String {
+    let mut output = String::new();
+
+    for y in 0..matrix.height() {
+        for x in 0..matrix.width {
+            let letter = matrix.letter_at(x, y).to_char();
+            if highlighted.contains(&(x, y)) {
+                output.push(letter);
+                continue;
+            }
+            match style {
+                RenderStyle::Dotted => output.push('.'),
+                RenderStyle::Dimmed => {
+                    output.push_str(DIM);
+                    output.push(letter);
+                    output.push_str(RESET);
+                },
+            }
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{render_template_matches, render_words, sample_input, template::Orientations, SearchBackend, X_MAS};
+
+    // The sample illustrations from the puzzle text
+    const XMAS_SAMPLE_ILLUSTRATION: &str = "\
+....XXMAS.
+.SAMXMS...
+...S..A...
+..A.A.MS.X
+XMASAMX.MM
+X.....XA.A
+S.S.S.S.SS
+.A.A.A.A.A
+..M.M.M.MM
+.X.X.XMASX
+";
+
+    const X_MAS_SAMPLE_ILLUSTRATION: &str = "\
+.M.S......
+..A..MSMS.
+.M.S.MAA..
+..A.ASMSM.
+.M.S.M....
+..........
+S.S.S.S.S.
+.A.A.A.A..
+M.M.M.M.M.
+..........
+";
+
+    #[test]
+    fn sample_illustrations() {
+        for backend in [SearchBackend::Automaton, SearchBackend::Bitboard] {
+            assert_eq!(render_words(sample_input(), &["XMAS"], backend, RenderStyle::Dotted), XMAS_SAMPLE_ILLUSTRATION);
+        }
+        assert_eq!(render_template_matches(sample_input(), X_MAS, Orientations::All, RenderStyle::Dotted), X_MAS_SAMPLE_ILLUSTRATION);
+    }
+
+    #[test]
+    fn styles() {
+        for backend in [SearchBackend::Automaton, SearchBackend::Bitboard] {
+            assert_eq!(render_words("XMAX\n", &["MA"], backend, RenderStyle::Dimmed), "\x1b[2mX\x1b[0mMA\x1b[2mX\x1b[0m\n");
+            // Multi-byte letters keep the grid geometry intact
+            assert_eq!(render_words("ÄBÇ\nДÉЖ\n語ЖÄ\n", &["ÇÉ語"], backend, RenderStyle::Dotted), "..Ç\n.É.\n語..\n");
+        }
+    }
+}
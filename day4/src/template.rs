@@ -0,0 +1,166 @@
+use crate::{Letter, LetterMatrix};
+
+// Two-dimensional stencil matching on a letter matrix.
+//
+// A template is a small grid of letters where '.' matches any letter, e.g.
+// the X-MAS cross:
+//
+//   M.S
+//   .A.
+//   M.S
+//
+// Templates can optionally be matched in all rotations and reflections.
+// Symmetric templates produce identical variants, which are only kept once
+// so that a single occurrence is never reported more than once.
+
+const WILDCARD: char = '.';
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Template<L: Letter = u8> {
+    width: usize,
+    height: usize,
+    cells: Vec<Option<L>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Orientations {
+    Fixed,
+    All,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TemplateMatch {
+    pub x: usize,
+    pub y: usize,
+    pub variant: usize,
+}
+
+impl<L: Letter> Template<L> {
+    pub fn parse(pattern: &str) -> Template<L> {
+        let rows: Vec<Vec<L>> = pattern.lines().map(L::decode).collect();
+        let height = rows.len();
+        let width = rows.first().map_or(0, |row| row.len());
+
+        assert!(width > 0 && height > 0, "Empty template");
+        assert!(rows.iter().all(|row| row.len() == width), "Template rows must have equal length");
+
+        Template {
+            width,
+            height,
+            cells: rows.iter()
+                .flat_map(|row| row.iter())
+                .map(|&letter| if letter.to_char() == WILDCARD { None } else { Some(letter) })
+                .collect(),
+        }
+    }
+
+    fn cell(&self, x: usize, y: usize) -> Option<L> {
+        self.cells[y * self.width + x]
+    }
+
+    fn rotated(&self) -> Template<L> {
+        // Clockwise: the left column, read bottom to top, becomes the top row
+        let mut cells = Vec::with_capacity(self.cells.len());
+        for x in 0..self.width {
+            for y in (0..self.height).rev() {
+                cells.push(self.cell(x, y));
+            }
+        }
+        Template { width: self.height, height: self.width, cells }
+    }
+
+    fn reflected(&self) -> Template<L> {
+        let mut cells = Vec::with_capacity(self.cells.len());
+        for y in 0..self.height {
+            for x in (0..self.width).rev() {
+                cells.push(self.cell(x, y));
+            }
+        }
+        Template { width: self.width, height: self.height, cells }
+    }
+
+    fn variants(&self, orientations: Orientations) -> Vec<Template<L>> {
+        if orientations == Orientations::Fixed {
+            return vec![self.clone()];
+        }
+
+        let mut variants: Vec<Template<L>> = Vec::new();
+        for start in [self.clone(), self.reflected()] {
+            let mut variant = start;
+            for _ in 0..4 {
+                let next = variant.rotated();
+                if !variants.contains(&variant) {
+                    variants.push(variant);
+                }
+                variant = next;
+            }
+        }
+        variants
+    }
+
+    fn matches_at(&self, matrix: &LetterMatrix<L>, x: usize, y: usize) -> bool {
+        (0..self.height).all(|dy| {
+            (0..self.width).all(|dx| {
+                self.cell(dx, dy).is_none_or(|letter| matrix.letter_at(x + dx, y + dy) == letter)
+            })
+        })
+    }
+}
+
+pub struct TemplateMatcher<L: Letter = u8> {
+    variants: Vec<Template<L>>,
+}
+
+impl<L: Letter> TemplateMatcher<L> {
+    pub fn new(template: &Template<L>, orientations: Orientations) -> TemplateMatcher<L> {
+        TemplateMatcher { variants: template.variants(orientations) }
+    }
+
+    pub fn find_all(&self, matrix: &LetterMatrix<L>) -> Vec<TemplateMatch> {
+        let mut matches = Vec::new();
+
+        for (variant_index, variant) in self.variants.iter().enumerate() {
+            if variant.width > matrix.width || variant.height > matrix.height() {
+                continue;
+            }
+            for y in 0..=(matrix.height() - variant.height) {
+                for x in 0..=(matrix.width - variant.width) {
+                    if variant.matches_at(matrix, x, y) {
+                        matches.push(TemplateMatch { x, y, variant: variant_index });
+                    }
+                }
+            }
+        }
+
+        matches
+    }
+
+    pub fn cells(&self, template_match: &TemplateMatch) -> Vec<(usize, usize)> {
+        let variant = &self.variants[template_match.variant];
+        let mut cells = Vec::new();
+        for dy in 0..variant.height {
+            for dx in 0..variant.width {
+                if variant.cell(dx, dy).is_some() {
+                    cells.push((template_match.x + dx, template_match.y + dy));
+                }
+            }
+        }
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{count_template_matches, sample_input, X_MAS};
+
+    #[test]
+    fn orientations() {
+        assert_eq!(count_template_matches(sample_input(), X_MAS, Orientations::All), 9);
+        // A plus sign is symmetric under every rotation and reflection, so it
+        // is only reported once per position
+        assert_eq!(count_template_matches(".A.\nAAA\n.A.\n", ".A.\nAAA\n.A.\n", Orientations::All), 1);
+        assert_eq!(count_template_matches("MXS\nXAX\nMXS\n", X_MAS, Orientations::Fixed), 1);
+        assert_eq!(count_template_matches("SXS\nXAX\nMXM\n", X_MAS, Orientations::Fixed), 0);
+    }
+}
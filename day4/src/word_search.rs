@@ -0,0 +1,213 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{get_cell_offset, Direction, Letter, LetterMatrix, LinearIterator, ReadDirection, Region};
+
+// Multi-pattern word search over the linear iterators of a letter matrix.
+//
+// Every word is turned into one or two patterns: the word itself (read
+// forward) and its reverse (which matches the word read backward along
+// the same line). The patterns are compiled into an Aho-Corasick automaton,
+// so a single pass over each of the four line directions finds every word in
+// all eight reading directions, including overlapping occurrences.
+
+// The start cell is where the first letter of the word is, so for backward
+// reads it's the cell furthest along the line direction
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct WordMatch {
+    pub word: usize,
+    pub x: usize,
+    pub y: usize,
+    pub direction: Direction,
+    pub read_direction: ReadDirection,
+    pub length: usize,
+}
+
+impl WordMatch {
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (mut dx, mut dy) = get_cell_offset(&self.direction);
+        if self.read_direction == ReadDirection::Backward {
+            (dx, dy) = (-dx, -dy);
+        }
+        (0..self.length as isize).map(move |step| {
+            (self.x.wrapping_add_signed(dx * step), self.y.wrapping_add_signed(dy * step))
+        })
+    }
+}
+
+pub struct Pattern<L: Letter> {
+    pub letters: Vec<L>,
+    pub origins: Vec<(usize, ReadDirection)>,
+}
+
+// Each distinct letter sequence becomes a single pattern, which remembers
+// every word (and the reading direction) it stands for
+pub fn word_patterns<L: Letter>(words: &[&str]) -> Vec<Pattern<L>> {
+    let mut patterns: Vec<Pattern<L>> = Vec::new();
+    let mut pattern_indices: HashMap<Vec<L>, usize> = HashMap::new();
+
+    for (word_index, word) in words.iter().enumerate() {
+        assert!(!word.is_empty(), "Empty words cannot be searched for");
+
+        let forward = L::decode(word);
+        let backward: Vec<L> = forward.iter().rev().copied().collect();
+
+        // Palindromes read the same in both directions, so only the
+        // forward reading is recorded to avoid counting them twice
+        let mut readings = vec![(forward, ReadDirection::Forward)];
+        if backward != readings[0].0 {
+            readings.push((backward, ReadDirection::Backward));
+        }
+
+        for (letters, read_direction) in readings {
+            let pattern_index = *pattern_indices.entry(letters.clone()).or_insert_with(|| {
+                patterns.push(Pattern { letters, origins: Vec::new() });
+                patterns.len() - 1
+            });
+            patterns[pattern_index].origins.push((word_index, read_direction));
+        }
+    }
+
+    patterns
+}
+
+struct State<L: Letter> {
+    transitions: HashMap<L, usize>,
+    failure: usize,
+    outputs: Vec<usize>,
+}
+
+pub struct WordSearch<L: Letter> {
+    states: Vec<State<L>>,
+    patterns: Vec<Pattern<L>>,
+}
+
+impl<L: Letter> WordSearch<L> {
+    pub fn new(words: &[&str]) -> WordSearch<L> {
+        let mut search = WordSearch {
+            states: vec![State { transitions: HashMap::new(), failure: 0, outputs: Vec::new() }],
+            patterns: Vec::new(),
+        };
+
+        for (pattern_index, pattern) in word_patterns(words).into_iter().enumerate() {
+            search.insert(&pattern.letters, pattern_index);
+            search.patterns.push(pattern);
+        }
+
+        search.link_failures();
+        search
+    }
+
+    fn insert(&mut self, letters: &[L], pattern_index: usize) {
+        let mut state = 0;
+        for letter in letters {
+            state = match self.states[state].transitions.get(letter) {
+                Some(&next) => next,
+                None => {
+                    self.states.push(State { transitions: HashMap::new(), failure: 0, outputs: Vec::new() });
+                    let next = self.states.len() - 1;
+                    self.states[state].transitions.insert(*letter, next);
+                    next
+                }
+            };
+        }
+        self.states[state].outputs.push(pattern_index);
+    }
+
+    // Breadth-first, so the failure state of every parent is known before
+    // its children are visited
+    fn link_failures(&mut self) {
+        let mut queue: VecDeque<usize> = self.states[0].transitions.values().copied().collect();
+
+        while let Some(state) = queue.pop_front() {
+            let transitions: Vec<(L, usize)> = self.states[state].transitions.iter().map(|(&letter, &next)| (letter, next)).collect();
+            for (letter, next) in transitions {
+                let failure = if state == 0 { 0 } else { self.step(self.states[state].failure, letter) };
+                self.states[next].failure = failure;
+                let inherited_outputs = self.states[failure].outputs.clone();
+                self.states[next].outputs.extend(inherited_outputs);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    fn step(&self, mut state: usize, letter: L) -> usize {
+        loop {
+            if let Some(&next) = self.states[state].transitions.get(&letter) {
+                return next;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = self.states[state].failure;
+        }
+    }
+
+    pub fn find_in(&self, letters: LinearIterator<L>) -> Vec<WordMatch> {
+        let direction = letters.direction;
+        let matrix = letters.matrix;
+        let mut matches = Vec::new();
+        let mut state = 0;
+        let mut line_indices = Vec::new();
+
+        for (index, letter) in letters {
+            if letter == L::NEWLINE {
+                state = 0;
+                line_indices.clear();
+                continue;
+            }
+
+            line_indices.push(index);
+            state = self.step(state, letter);
+
+            for &pattern_index in &self.states[state].outputs {
+                let pattern = &self.patterns[pattern_index];
+                let length = pattern.letters.len();
+
+                // Single letters occupy the same cell in every direction,
+                // so they are only reported once, in the rows
+                if length == 1 && direction != Direction::Horizontal {
+                    continue;
+                }
+
+                let first = line_indices[line_indices.len() - length];
+                for &(word, read_direction) in &pattern.origins {
+                    let (x, y) = matrix.position_of(match read_direction {
+                        ReadDirection::Forward => first,
+                        ReadDirection::Backward => index,
+                    });
+                    matches.push(WordMatch { word, x, y, direction, read_direction, length });
+                }
+            }
+        }
+
+        matches
+    }
+
+    pub fn find_all(&self, matrix: &LetterMatrix<L>) -> Vec<WordMatch> {
+        self.find_in_region(matrix.whole())
+    }
+
+    // Only words lying entirely within the region are found
+    pub fn find_in_region(&self, region: Region<L>) -> Vec<WordMatch> {
+        let mut matches = self.find_in(region.rows());
+        matches.extend(self.find_in(region.columns()));
+        matches.extend(self.find_in(region.diagonals()));
+        matches.extend(self.find_in(region.anti_diagonals()));
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Only words lying entirely inside the region are found, at their
+    // position in the whole matrix
+    #[test]
+    fn region_search() {
+        let matrix = LetterMatrix::new("ABC\nDEF\nGHI\n").unwrap();
+        let scoped_matches = WordSearch::new(&["EH", "DE"]).find_in_region(matrix.region(1, 1, 2, 2).unwrap());
+        assert_eq!(scoped_matches.len(), 1);
+        assert_eq!((scoped_matches[0].x, scoped_matches[0].y), (1, 1));
+    }
+}
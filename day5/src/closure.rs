@@ -0,0 +1,124 @@
+use std::{collections::{HashMap, HashSet}, ops::BitOrAssign};
+
+use crate::{OrderingRules, Page, Rule};
+use crate::violations::find_violations;
+
+// Transitive closure of a rule set, one bitset row per page: bit b of the
+// row of page a is set if a must come before b through any chain of rules.
+//
+// Pages on a cycle in the rules end up preceding each other (and
+// themselves), which is exactly what the real input does when taken as a
+// whole. Restricting the closure to the pages of an update avoids that.
+
+pub struct PrecedenceClosure<P: Page> {
+    page_numbers: HashMap<P, usize>,
+    rows: Vec<Vec<u64>>,
+}
+
+fn has_bit(row: &[u64], bit: usize) -> bool {
+    row[bit / 64] & (1 << (bit % 64)) != 0
+}
+
+// Warshall, a whole row of successors at a time, on rows of bools or of
+// bitset blocks alike. `precedes(row, via)` tells whether the row's page
+// comes before page `via`
+pub fn close_transitively<T: Copy + BitOrAssign>(rows: &mut [Vec<T>], precedes: impl Fn(&[T], usize) -> bool) {
+    for via in 0..rows.len() {
+        let via_row = rows[via].clone();
+        for row in rows.iter_mut().filter(|row| precedes(row, via)) {
+            row.iter_mut().zip(&via_row).for_each(|(block, &via_block)| *block |= via_block);
+        }
+    }
+}
+
+impl<P: Page> PrecedenceClosure<P> {
+    pub fn new(ordering_rules: &OrderingRules<P>) -> PrecedenceClosure<P> {
+        Self::build(ordering_rules, |_| true)
+    }
+
+    pub fn for_update(ordering_rules: &OrderingRules<P>, page_list: &[P]) -> PrecedenceClosure<P> {
+        Self::build(ordering_rules, |page| page_list.contains(page))
+    }
+
+    fn build(ordering_rules: &OrderingRules<P>, is_relevant: impl Fn(&P) -> bool) -> PrecedenceClosure<P> {
+        let mut page_numbers: HashMap<P, usize> = HashMap::new();
+        let rules: Vec<&Rule<P>> = ordering_rules.rules.iter()
+            .filter(|rule| is_relevant(&rule.before) && is_relevant(&rule.after))
+            .collect();
+        for rule in &rules {
+            for page in [&rule.before, &rule.after] {
+                let next_number = page_numbers.len();
+                page_numbers.entry(page.clone()).or_insert(next_number);
+            }
+        }
+
+        let blocks = page_numbers.len().div_ceil(64);
+        let mut rows = vec![vec![0u64; blocks]; page_numbers.len()];
+        for rule in &rules {
+            let after = page_numbers[&rule.after];
+            rows[page_numbers[&rule.before]][after / 64] |= 1 << (after % 64);
+        }
+
+        close_transitively(&mut rows, has_bit);
+
+        PrecedenceClosure { page_numbers, rows }
+    }
+
+    pub fn must_precede(&self, a: &P, b: &P) -> bool {
+        match (self.page_numbers.get(a), self.page_numbers.get(b)) {
+            (Some(&a), Some(&b)) => has_bit(&self.rows[a], b),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct UpdateChange<P: Page> {
+    pub update: Vec<P>,
+    pub was_valid: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RuleDiff<P: Page> {
+    // Rules only in the new set, with their new line numbers
+    pub added: Vec<Rule<P>>,
+    // Rules only in the old set, with their old line numbers
+    pub removed: Vec<Rule<P>>,
+    // Orders within an update that follow from the new rules but didn't
+    // follow from the old ones, without being a rule themselves
+    pub now_implied: Vec<(P, P)>,
+    // Updates that are valid under one rule set but not the other
+    pub changed_updates: Vec<UpdateChange<P>>,
+}
+
+pub fn diff_rules<P: Page>(old: &OrderingRules<P>, new: &OrderingRules<P>, updates: &[Vec<P>]) -> RuleDiff<P> {
+    let added = new.rules.iter().filter(|rule| !old.must_precede(&rule.before, &rule.after)).cloned().collect();
+    let removed = old.rules.iter().filter(|rule| !new.must_precede(&rule.before, &rule.after)).cloned().collect();
+
+    // Closures over whole rule sets are useless when the rules are cyclic
+    // as a whole, like the real ones, so they're taken per update, which is
+    // also the only place where the puzzle applies rules
+    let mut now_implied = Vec::new();
+    let mut seen = HashSet::new();
+    for update in updates {
+        let old_closure = PrecedenceClosure::for_update(old, update);
+        let new_closure = PrecedenceClosure::for_update(new, update);
+        for a in update {
+            for b in update {
+                if new_closure.must_precede(a, b) && !old_closure.must_precede(a, b) && !new.must_precede(a, b) && seen.insert((a, b)) {
+                    now_implied.push((a.clone(), b.clone()));
+                }
+            }
+        }
+    }
+
+    let changed_updates = updates.iter()
+        .filter_map(|update| {
+            let was_valid = find_violations(update, old).is_empty();
+            let is_valid = find_violations(update, new).is_empty();
+            (was_valid != is_valid).then(|| UpdateChange { update: update.clone(), was_valid })
+        })
+        .collect();
+
+    RuleDiff { added, removed, now_implied, changed_updates }
+}
@@ -0,0 +1,151 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{OrderingRules, Page, Rule};
+
+// Rule sets are checked for cycles by finding their strongly connected
+// components (Tarjan's algorithm). Every component with more than one page,
+// or a page that must precede itself, is a set of pages no update can order.
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cycle<P: Page> {
+    pub pages: Vec<P>,
+    pub rules: Vec<Rule<P>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InconsistentRules<P: Page> {
+    pub cycles: Vec<Cycle<P>>,
+}
+
+impl<P: Page + fmt::Display> fmt::Display for InconsistentRules<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for cycle in &self.cycles {
+            let pages: Vec<String> = cycle.pages.iter().map(|page| page.to_string()).collect();
+            writeln!(f, "pages {} form a cycle in the ordering rules:", pages.join(", "))?;
+            for rule in &cycle.rules {
+                writeln!(f, "  line {}: {}|{}", rule.line, rule.before, rule.after)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Tarjan {
+    successors: Vec<Vec<usize>>,
+    next_index: usize,
+    indices: Vec<Option<usize>>,
+    low_links: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    components: Vec<Vec<usize>>,
+}
+
+impl Tarjan {
+    fn visit(&mut self, node: usize) {
+        self.indices[node] = Some(self.next_index);
+        self.low_links[node] = self.next_index;
+        self.next_index += 1;
+        self.stack.push(node);
+        self.on_stack[node] = true;
+
+        for successor_index in 0..self.successors[node].len() {
+            let successor = self.successors[node][successor_index];
+            match self.indices[successor] {
+                None => {
+                    self.visit(successor);
+                    self.low_links[node] = self.low_links[node].min(self.low_links[successor]);
+                },
+                Some(index) if self.on_stack[successor] => {
+                    self.low_links[node] = self.low_links[node].min(index);
+                },
+                _ => {},
+            }
+        }
+
+        if Some(self.low_links[node]) == self.indices[node] {
+            let mut component = Vec::new();
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack[member] = false;
+                component.push(member);
+                if member == node {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+// Pages are numbered in order of their first appearance in the rules, which
+// also determines the order in which cycles and their pages are reported
+pub fn find_cycles<P: Page>(ordering_rules: &OrderingRules<P>, is_relevant: impl Fn(&P) -> bool) -> Vec<Cycle<P>> {
+    let rules: Vec<Rule<P>> = ordering_rules.rules.iter()
+        .filter(|rule| is_relevant(&rule.before) && is_relevant(&rule.after))
+        .cloned()
+        .collect();
+
+    find_order_cycles(rules, |rule| (&rule.before, &rule.after)).into_iter()
+        .map(|(pages, rules)| Cycle { pages, rules })
+        .collect()
+}
+
+// The same for orders between pages that aren't rules, e.g. orders seen in
+// updates, with `ends` giving the page that comes before and the one after
+pub fn find_order_cycles<P: Page, O>(orders: Vec<O>, ends: impl Fn(&O) -> (&P, &P)) -> Vec<(Vec<P>, Vec<O>)> {
+    let mut pages: Vec<P> = Vec::new();
+    let mut page_numbers: HashMap<P, usize> = HashMap::new();
+    let mut number_of = |page: &P| *page_numbers.entry(page.clone()).or_insert_with(|| {
+        pages.push(page.clone());
+        pages.len() - 1
+    });
+    let edges: Vec<(usize, usize)> = orders.iter().map(|order| {
+        let (before, after) = ends(order);
+        (number_of(before), number_of(after))
+    }).collect();
+
+    let mut tarjan = Tarjan {
+        successors: vec![Vec::new(); pages.len()],
+        next_index: 0,
+        indices: vec![None; pages.len()],
+        low_links: vec![0; pages.len()],
+        stack: Vec::new(),
+        on_stack: vec![false; pages.len()],
+        components: Vec::new(),
+    };
+    for &(before, after) in &edges {
+        tarjan.successors[before].push(after);
+    }
+    for node in 0..pages.len() {
+        if tarjan.indices[node].is_none() {
+            tarjan.visit(node);
+        }
+    }
+
+    let mut component_of = vec![0; pages.len()];
+    for (component_index, component) in tarjan.components.iter().enumerate() {
+        for &member in component {
+            component_of[member] = component_index;
+        }
+    }
+
+    let mut component_orders: Vec<Vec<O>> = tarjan.components.iter().map(|_| Vec::new()).collect();
+    for (order, (before, after)) in orders.into_iter().zip(edges) {
+        if component_of[before] == component_of[after] {
+            component_orders[component_of[before]].push(order);
+        }
+    }
+
+    let mut cycles: Vec<(Vec<usize>, Vec<O>)> = tarjan.components.into_iter().zip(component_orders)
+        .filter(|(_, orders)| !orders.is_empty())
+        .map(|(mut component, orders)| {
+            component.sort_unstable();
+            (component, orders)
+        })
+        .collect();
+    cycles.sort_unstable_by_key(|(component, _)| component[0]);
+
+    cycles.into_iter()
+        .map(|(component, orders)| (component.iter().map(|&page| pages[page].clone()).collect(), orders))
+        .collect()
+}
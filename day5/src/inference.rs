@@ -0,0 +1,132 @@
+use std::{collections::HashMap, fmt::{self, Display}};
+
+use crate::{closure::close_transitively, cycles::find_order_cycles, Page};
+
+// Derives ordering rules from updates that are known to be correctly
+// ordered.
+//
+// Every pair of pages seen together is a witness that the first one comes
+// before the second. Pairs seen in both orders can't be ruled on. The rest
+// are reduced to the fewest rules that still imply all of them transitively,
+// e.g. 1|2 and 2|3 without 1|3. Note that the puzzle only applies rules to
+// pages that are both in an update, so the reduced rules only check updates
+// that contain the intermediate pages as well.
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InferredRules<P: Page> {
+    pub rules: Vec<(P, P)>,
+    // Seen in both orders
+    pub conflicting: Vec<(P, P)>,
+    // Never seen together, and not ordered through other pages either
+    pub unwitnessed: Vec<(P, P)>,
+}
+
+// Two pages in the order an update has them in, by its index in the list
+#[derive(Debug, PartialEq, Eq)]
+pub struct WitnessedOrder<P: Page> {
+    pub before: P,
+    pub after: P,
+    pub update: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct OrderCycle<P: Page> {
+    pub pages: Vec<P>,
+    pub orders: Vec<WitnessedOrder<P>>,
+}
+
+// Updates that, between them, put pages in a circular order, so no rules
+// can agree with all of them
+#[derive(Debug, PartialEq, Eq)]
+pub struct ContradictoryUpdates<P: Page> {
+    pub cycles: Vec<OrderCycle<P>>,
+}
+
+impl<P: Page + Display> Display for ContradictoryUpdates<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for cycle in &self.cycles {
+            let pages: Vec<String> = cycle.pages.iter().map(|page| page.to_string()).collect();
+            writeln!(f, "pages {} are ordered in a cycle by the updates:", pages.join(", "))?;
+            for order in &cycle.orders {
+                writeln!(f, "  update {}: {} before {}", order.update + 1, order.before, order.after)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn infer_rules<P: Page>(updates: &[Vec<P>]) -> Result<InferredRules<P>, ContradictoryUpdates<P>> {
+    let mut pages: Vec<P> = Vec::new();
+    let mut page_numbers: HashMap<P, usize> = HashMap::new();
+    // The first update each pair of pages is seen in, in that order
+    let mut witnessed: HashMap<(usize, usize), usize> = HashMap::new();
+
+    for (update_index, update) in updates.iter().enumerate() {
+        let numbers: Vec<usize> = update.iter()
+            .map(|page| *page_numbers.entry(page.clone()).or_insert_with(|| {
+                pages.push(page.clone());
+                pages.len() - 1
+            }))
+            .collect();
+        for (position, &a) in numbers.iter().enumerate() {
+            for &b in &numbers[position + 1..] {
+                witnessed.entry((a, b)).or_insert(update_index);
+            }
+        }
+    }
+
+    let count = pages.len();
+    let mut precedes = vec![vec![false; count]; count];
+    let mut conflicting = Vec::new();
+    for &(a, b) in witnessed.keys() {
+        if !witnessed.contains_key(&(b, a)) {
+            precedes[a][b] = true;
+        } else if a < b {
+            conflicting.push((a, b));
+        }
+    }
+
+    // Orders seen in different updates may still contradict each other
+    // through a third page
+    let orders: Vec<WitnessedOrder<P>> = (0..count)
+        .flat_map(|a| (0..count).map(move |b| (a, b)))
+        .filter(|&(a, b)| precedes[a][b])
+        .map(|(a, b)| WitnessedOrder { before: pages[a].clone(), after: pages[b].clone(), update: witnessed[&(a, b)] })
+        .collect();
+    let cycles: Vec<OrderCycle<P>> = find_order_cycles(orders, |order| (&order.before, &order.after)).into_iter()
+        .map(|(pages, orders)| OrderCycle { pages, orders })
+        .collect();
+    if !cycles.is_empty() {
+        return Err(ContradictoryUpdates { cycles });
+    }
+
+    close_transitively(&mut precedes, |row, via| row[via]);
+
+    let mut rules = Vec::new();
+    let mut unwitnessed = Vec::new();
+    for a in 0..count {
+        for b in 0..count {
+            let is_implied = (0..count).any(|via| precedes[a][via] && precedes[via][b]);
+            if precedes[a][b] && !is_implied {
+                rules.push((a, b));
+            }
+            let is_seen = witnessed.contains_key(&(a, b)) || witnessed.contains_key(&(b, a));
+            if a < b && !is_seen && !precedes[a][b] && !precedes[b][a] {
+                unwitnessed.push((a, b));
+            }
+        }
+    }
+
+    conflicting.sort_unstable();
+    let to_pages = |pairs: Vec<(usize, usize)>| pairs.into_iter().map(|(a, b)| (pages[a].clone(), pages[b].clone())).collect();
+    Ok(InferredRules {
+        rules: to_pages(rules),
+        conflicting: to_pages(conflicting),
+        unwitnessed: to_pages(unwitnessed),
+    })
+}
+
+// In the same a|b format as the puzzle input
+pub fn format_rules<P: Page + Display>(rules: &[(P, P)]) -> String {
+    rules.iter().map(|(before, after)| format!("{before}|{after}\n")).collect()
+}
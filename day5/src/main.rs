@@ -1,48 +1,94 @@
+use std::{collections::{BTreeSet, HashMap, HashSet}, fmt::Debug, hash::Hash, str::FromStr};
+
+use closure::{diff_rules, PrecedenceClosure, UpdateChange};
+use cycles::{find_cycles, Cycle, InconsistentRules};
+use inference::{format_rules, infer_rules};
+use orderings::{count_valid_orderings, valid_orderings, OrderingsError};
+use print_queue::{ParseError, PrintQueue};
+use violations::{apply_moves, check_order, Move};
+
+mod closure;
+mod cycles;
+mod inference;
+mod orderings;
+mod print_queue;
+mod violations;
+
 fn input() -> &'static str {
     include_str!("../inputs/1.txt")
 }
 
-type OrderingRules = [Vec<u8>; 100];
-type PageList = Vec<u8>;
+fn sample_input() -> &'static str {
+    include_str!("../inputs/sample.txt")
+}
 
-fn read_rules_and_process_lists<F>(mut predicate: F)
-where F: FnMut(&OrderingRules, PageList) {
-    enum ReadPhase {
-        OrderingRules,
-        PageLists,
-    }
+// Anything that can be parsed from the input can be a page identifier,
+// e.g. integers of any width or plain strings
+trait Page: Clone + Eq + Hash + Debug + FromStr<Err: Debug> {}
 
-    let mut phase = ReadPhase::OrderingRules;
-    let mut ordering_rules: OrderingRules = [const { Vec::new() }; 100];
+impl<T: Clone + Eq + Hash + Debug + FromStr<Err: Debug>> Page for T {}
 
-    input().lines().for_each(|line| {
-        if line.is_empty() {
-            phase = ReadPhase::PageLists;
-            return
-        }
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Rule<P: Page> {
+    before: P,
+    after: P,
+    // Line number in the input, starting at 1
+    line: usize,
+}
 
-        match phase {
-            ReadPhase::OrderingRules => {
-                let mut split = line.split("|");
-                let left = split.next().unwrap().parse::<u8>().unwrap();
-                let right = split.next().unwrap().parse::<u8>().unwrap();
-                ordering_rules[left as usize].push(right);
-            }
-            ReadPhase::PageLists => {
-                predicate(&ordering_rules, line.split(',').map(|value| value.parse::<u8>().unwrap()).collect());
-            }
-        }
-    });
+// Rule graph keyed by page: each page maps to the pages it must precede
+// (and the line of that rule), so checking a single rule is a hash lookup.
+// The rules are kept in input order as well, to be able to point at them
+// when they conflict.
+struct OrderingRules<P: Page> {
+    successors: HashMap<P, HashMap<P, usize>>,
+    rules: Vec<Rule<P>>,
 }
 
-fn get_correct_pair_order(ordering_rules: &OrderingRules, a: u8, b: u8) -> std::cmp::Ordering {
-    let a_should_precede_b = ordering_rules[a as usize].contains(&b);
-    let b_should_precede_a = ordering_rules[b as usize].contains(&a);
+impl<P: Page> OrderingRules<P> {
+    fn new() -> OrderingRules<P> {
+        OrderingRules { successors: HashMap::new(), rules: Vec::new() }
+    }
+
+    fn add(&mut self, before: P, after: P, line: usize) {
+        self.successors.entry(before.clone()).or_default().insert(after.clone(), line);
+        self.rules.push(Rule { before, after, line });
+    }
+
+    fn must_precede(&self, a: &P, b: &P) -> bool {
+        self.successors.get(a).is_some_and(|successors| successors.contains_key(b))
+    }
 
-    if a_should_precede_b && b_should_precede_a {
-        panic!("Inconsistent ordering rules");
+    fn rule(&self, before: &P, after: &P) -> Option<Rule<P>> {
+        self.successors.get(before)
+            .and_then(|successors| successors.get(after))
+            .map(|&line| Rule { before: before.clone(), after: after.clone(), line })
     }
 
+    fn validate(&self) -> Result<(), InconsistentRules<P>> {
+        Self::to_result(find_cycles(self, |_| true))
+    }
+
+    // Only the rules between pages of the update, which is all that matters
+    // for ordering it
+    fn validate_for(&self, page_list: &[P]) -> Result<(), InconsistentRules<P>> {
+        let pages: HashSet<&P> = page_list.iter().collect();
+        Self::to_result(find_cycles(self, |page| pages.contains(page)))
+    }
+
+    fn to_result(cycles: Vec<Cycle<P>>) -> Result<(), InconsistentRules<P>> {
+        if cycles.is_empty() { Ok(()) } else { Err(InconsistentRules { cycles }) }
+    }
+}
+
+type PageList<P> = Vec<P>;
+
+fn get_correct_pair_order<P: Page>(ordering_rules: &OrderingRules<P>, a: &P, b: &P) -> std::cmp::Ordering {
+    let a_should_precede_b = ordering_rules.must_precede(a, b);
+    let b_should_precede_a = ordering_rules.must_precede(b, a);
+
+    debug_assert!(!(a_should_precede_b && b_should_precede_a), "Conflicting rules should have been rejected by validate_for");
+
     if a_should_precede_b {
         std::cmp::Ordering::Less
     } else if b_should_precede_a {
@@ -52,41 +98,259 @@ fn get_correct_pair_order(ordering_rules: &OrderingRules, a: u8, b: u8) -> std::
     }
 }
 
-fn is_page_list_correctly_ordered(page_list: &PageList, ordering_rules: &OrderingRules) -> bool {
+fn is_page_list_correctly_ordered<P: Page>(page_list: &PageList<P>, ordering_rules: &OrderingRules<P>) -> bool {
     page_list.windows(2).all(|window| {
-        get_correct_pair_order(ordering_rules, window[0], window[1]) == std::cmp::Ordering::Less
+        get_correct_pair_order(ordering_rules, &window[0], &window[1]) == std::cmp::Ordering::Less
     })
 }
 
-fn day5_1() -> u32 {
-    let mut sum_of_correctly_ordered_middle_values = 0;
+// Whenever more than one page could come next, the candidates are recorded
+// as an ambiguity. The chosen order prefers the page that came first in the
+// original list, so already ordered stretches are left alone.
+#[derive(Debug, PartialEq, Eq)]
+struct Reordering<P: Page> {
+    pages: PageList<P>,
+    ambiguities: Vec<Vec<P>>,
+}
 
-    read_rules_and_process_lists(|ordering_rules, page_list| {
-        if is_page_list_correctly_ordered(&page_list, ordering_rules) {
-            sum_of_correctly_ordered_middle_values += page_list[page_list.len() / 2] as u32;
-        }
-    });
+impl<P: Page> Reordering<P> {
+    fn is_unique(&self) -> bool {
+        self.ambiguities.is_empty()
+    }
+}
 
-    sum_of_correctly_ordered_middle_values
+#[derive(Debug, PartialEq, Eq)]
+enum ReorderError<P: Page> {
+    // The pages that could not be placed, because they are part of a cycle
+    // in the rules or must come after one
+    NoValidOrder(Vec<P>),
 }
 
-fn day5_2() -> u32 {
-    let mut sum_of_reordered_middle_values = 0;
+// Kahn's algorithm on the rules restricted to the pages of the list
+fn reorder<P: Page>(page_list: &PageList<P>, ordering_rules: &OrderingRules<P>) -> Result<Reordering<P>, ReorderError<P>> {
+    let mut successors = vec![Vec::new(); page_list.len()];
+    let mut predecessor_counts = vec![0; page_list.len()];
+    for (a, page_a) in page_list.iter().enumerate() {
+        for (b, page_b) in page_list.iter().enumerate() {
+            if ordering_rules.must_precede(page_a, page_b) {
+                successors[a].push(b);
+                predecessor_counts[b] += 1;
+            }
+        }
+    }
+
+    let mut available: BTreeSet<usize> = (0..page_list.len()).filter(|&index| predecessor_counts[index] == 0).collect();
+    let mut reordering = Reordering { pages: Vec::with_capacity(page_list.len()), ambiguities: Vec::new() };
+
+    while let Some(index) = available.pop_first() {
+        if !available.is_empty() {
+            let mut candidates = vec![page_list[index].clone()];
+            candidates.extend(available.iter().map(|&other| page_list[other].clone()));
+            reordering.ambiguities.push(candidates);
+        }
 
-    read_rules_and_process_lists(|ordering_rules, mut page_list| {
-        if is_page_list_correctly_ordered(&page_list, ordering_rules) {
-            return;
+        reordering.pages.push(page_list[index].clone());
+        for &successor in &successors[index] {
+            predecessor_counts[successor] -= 1;
+            if predecessor_counts[successor] == 0 {
+                available.insert(successor);
+            }
         }
+    }
+
+    if reordering.pages.len() < page_list.len() {
+        let unplaced = (0..page_list.len())
+            .filter(|&index| predecessor_counts[index] > 0)
+            .map(|index| page_list[index].clone())
+            .collect();
+        return Err(ReorderError::NoValidOrder(unplaced));
+    }
 
-        page_list.sort_unstable_by(|a, b| get_correct_pair_order(ordering_rules, *a, *b));
+    Ok(reordering)
+}
+
+fn middle_page<P: Page>(page_list: &PageList<P>) -> &P {
+    &page_list[page_list.len() / 2]
+}
+
+fn sum_of_correctly_ordered_middle_pages(print_queue: &PrintQueue<u64>) -> Result<u64, InconsistentRules<u64>> {
+    print_queue.validate()?;
 
-        sum_of_reordered_middle_values += page_list[page_list.len() / 2] as u32;
-    });
+    Ok(print_queue.updates.iter()
+        .filter(|page_list| is_page_list_correctly_ordered(page_list, &print_queue.rules))
+        .map(middle_page)
+        .sum())
+}
+
+fn sum_of_reordered_middle_pages(print_queue: &PrintQueue<u64>) -> Result<u64, InconsistentRules<u64>> {
+    print_queue.validate()?;
+
+    Ok(print_queue.updates.iter()
+        .filter(|page_list| !is_page_list_correctly_ordered(page_list, &print_queue.rules))
+        .map(|page_list| *middle_page(&reorder(page_list, &print_queue.rules).unwrap().pages))
+        .sum())
+}
 
-    sum_of_reordered_middle_values
+fn day5_1(print_queue: &PrintQueue<u64>) -> u64 {
+    sum_of_correctly_ordered_middle_pages(print_queue).unwrap()
+}
+
+fn day5_2(print_queue: &PrintQueue<u64>) -> u64 {
+    sum_of_reordered_middle_pages(print_queue).unwrap()
 }
 
 fn main() {
-    assert_eq!(day5_1(), 5108);
-    assert_eq!(day5_2(), 7380);
+    let print_queue = PrintQueue::<u64>::parse(input()).unwrap();
+    let sample = PrintQueue::<u64>::parse(sample_input()).unwrap();
+
+    // Parsing round-trips, and tolerates CRLF and stray whitespace
+    assert_eq!(sample.to_string(), sample_input());
+    let untidy_input = sample_input().replace('\n', " \r\n").replacen("47|53", " 47 | 53", 1);
+    assert_eq!(PrintQueue::<u64>::parse(&untidy_input).unwrap().to_string(), sample_input());
+    assert_eq!(PrintQueue::<u64>::parse("1|2\n1-3\n").err(), Some(ParseError { line: 2, content: "1-3".to_string() }));
+    assert_eq!(PrintQueue::<u64>::parse("1|2\n\n1,x\n").err().unwrap().to_string(), "line 3 is neither a rule nor an update: \"1,x\"");
+
+    assert_eq!(sum_of_correctly_ordered_middle_pages(&sample), Ok(143));
+    assert_eq!(sum_of_reordered_middle_pages(&sample), Ok(123));
+    // Page numbers beyond a byte used to index out of bounds
+    let large_pages = PrintQueue::parse("100|2000\n2000|70000\n\n100,2000,70000\n70000,2000,100\n").unwrap();
+    assert_eq!(sum_of_correctly_ordered_middle_pages(&large_pages), Ok(2000));
+    let named_pages = PrintQueue::<String>::parse("intro|body\nbody|outro\n\nintro,body,outro\nbody,intro\n").unwrap();
+    let correctly_ordered_names: Vec<String> = named_pages.updates.iter()
+        .filter(|page_list| is_page_list_correctly_ordered(page_list, &named_pages.rules))
+        .map(|page_list| page_list.join(","))
+        .collect();
+    assert_eq!(correctly_ordered_names, ["intro,body,outro"]);
+
+    let partial_rules = PrintQueue::<u8>::parse("1|2\n2|3\n1|4\n3|1\n\n4,2,1\n3,4,1,2\n2,4\n").unwrap();
+    let reorderings: Vec<_> = partial_rules.updates.iter().map(|page_list| reorder(page_list, &partial_rules.rules)).collect();
+    assert_eq!(reorderings, [
+        Ok(Reordering { pages: vec![1, 4, 2], ambiguities: vec![vec![4, 2]] }),
+        Err(ReorderError::NoValidOrder(vec![3, 4, 1, 2])),
+        Ok(Reordering { pages: vec![2, 4], ambiguities: vec![vec![2, 4]] }),
+    ]);
+    assert!(!reorderings[0].as_ref().unwrap().is_unique());
+
+    // A three-page cycle is found even though no two rules contradict each
+    // other directly, and only matters to updates containing all three pages
+    let cyclic_rules = PrintQueue::parse("1|2\n2|3\n3|1\n3|4\n4|4\n\n1,2,3\n1,2\n").unwrap();
+    let three_page_cycle = Cycle {
+        pages: vec![1, 2, 3],
+        rules: vec![Rule { before: 1, after: 2, line: 1 }, Rule { before: 2, after: 3, line: 2 }, Rule { before: 3, after: 1, line: 3 }],
+    };
+    let self_cycle = Cycle { pages: vec![4], rules: vec![Rule { before: 4, after: 4, line: 5 }] };
+    assert_eq!(cyclic_rules.rules.validate().unwrap_err().cycles, [three_page_cycle, self_cycle]);
+    let update_validation = cyclic_rules.rules.validate_for(&cyclic_rules.updates[0]);
+    assert_eq!(update_validation.as_ref().unwrap_err().cycles.len(), 1);
+    assert_eq!(update_validation.unwrap_err().to_string(), "\
+pages 1, 2, 3 form a cycle in the ordering rules:
+  line 1: 1|2
+  line 2: 2|3
+  line 3: 3|1
+");
+    assert_eq!(cyclic_rules.rules.validate_for(&cyclic_rules.updates[1]), Ok(()));
+    assert!(cyclic_rules.validate().is_err());
+    assert!(sum_of_reordered_middle_pages(&cyclic_rules).is_err());
+
+    for page_list in &print_queue.updates {
+        let order_check = check_order(page_list, &print_queue.rules).unwrap();
+        assert!(is_page_list_correctly_ordered(&apply_moves(page_list, &order_check.moves), &print_queue.rules));
+    }
+    let order_checks: Vec<_> = sample.updates.iter().map(|page_list| check_order(page_list, &sample.rules).unwrap()).collect();
+    for (page_list, order_check) in sample.updates.iter().zip(&order_checks) {
+        assert!(is_page_list_correctly_ordered(&apply_moves(page_list, &order_check.moves), &sample.rules));
+        assert_eq!(order_check.violations.is_empty(), is_page_list_correctly_ordered(page_list, &sample.rules));
+    }
+    assert_eq!(order_checks[3].violations, [Rule { before: 97, after: 75, line: 16 }]);
+    assert_eq!(order_checks[3].moves, [Move { page: 75, after: Some(97) }]);
+    // 13 comes before all of 75, 29 and 47, and 29 before 47, but moving
+    // 13 and either 29 or 47 is enough
+    assert_eq!(order_checks[5].violations.len(), 4);
+    assert_eq!(order_checks[5].moves.len(), 2);
+    // Violations between pages that are not next to each other are listed
+    // too, and moving the single misplaced page fixes all of them
+    let misplaced_first = PrintQueue::parse("1|2\n1|3\n1|4\n\n2,3,4,1\n").unwrap();
+    let order_check = check_order(&misplaced_first.updates[0], &misplaced_first.rules).unwrap();
+    assert_eq!(order_check.violations.iter().map(|rule| rule.line).collect::<Vec<_>>(), [1, 2, 3]);
+    assert_eq!(order_check.moves, [Move { page: 1, after: None }]);
+    assert_eq!(apply_moves(&misplaced_first.updates[0], &order_check.moves), [1, 2, 3, 4]);
+
+    // The real rules leave no choice for any update
+    for page_list in &print_queue.updates {
+        assert_eq!(count_valid_orderings(page_list, &print_queue.rules, 1000), Ok(1));
+    }
+    let loose_rules = PrintQueue::parse("1|2\n3|4\n\n4,3,2,1\n1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20\n").unwrap();
+    let (few_pages, many_pages) = (&loose_rules.updates[0], &loose_rules.updates[1]);
+    // Half of all 24 permutations have 1 before 2, half of those 3 before 4
+    assert_eq!(count_valid_orderings(few_pages, &loose_rules.rules, 1000), Ok(6));
+    let orderings: Vec<Vec<u64>> = valid_orderings(few_pages, &loose_rules.rules).unwrap().collect();
+    assert_eq!(orderings, [[3, 4, 1, 2], [3, 1, 4, 2], [3, 1, 2, 4], [1, 3, 4, 2], [1, 3, 2, 4], [1, 2, 3, 4]]);
+    assert_eq!(count_valid_orderings(many_pages, &loose_rules.rules, 1_000_000_000), Err(OrderingsError::TooManyOrderings { cap: 1_000_000_000 }));
+    // Enumerating is lazy, so the first few are available regardless
+    assert_eq!(valid_orderings(many_pages, &loose_rules.rules).unwrap().take(3).count(), 3);
+    // An empty update has a single, empty order
+    assert_eq!(count_valid_orderings(&[], &loose_rules.rules, 1000), Ok(1));
+    assert_eq!(valid_orderings(&[], &loose_rules.rules).unwrap().collect::<Vec<_>>(), [Vec::<u64>::new()]);
+
+    let sample_updates: Vec<Vec<u64>> = sample.updates.iter()
+        .filter(|page_list| is_page_list_correctly_ordered(page_list, &sample.rules))
+        .cloned()
+        .collect();
+    let inferred = infer_rules(&sample_updates).unwrap();
+    assert_eq!(format_rules(&inferred.rules), "75|47\n47|61\n61|53\n53|29\n29|13\n97|61\n");
+    assert_eq!(inferred.unwitnessed, [(75, 97), (47, 97)]);
+    assert!(inferred.conflicting.is_empty());
+    // Re-reading the inferred rules accepts the updates they came from
+    let inferred_rules = PrintQueue::<u64>::parse(&format_rules(&inferred.rules)).unwrap().rules;
+    assert!(sample_updates.iter().all(|page_list| check_order(page_list, &inferred_rules).unwrap().violations.is_empty()));
+    let names = |update: &str| update.split(',').map(String::from).collect::<Vec<_>>();
+    let conflicting = infer_rules(&[names("a,b,c"), names("b,a")]).unwrap();
+    assert_eq!(format_rules(&conflicting.rules), "a|c\nb|c\n");
+    assert_eq!(conflicting.conflicting, [("a".to_string(), "b".to_string())]);
+    let cyclic = infer_rules(&[vec![1, 2], vec![2, 3], vec![3, 1]]).unwrap_err();
+    assert_eq!(cyclic.cycles[0].pages, [1, 2, 3]);
+    assert_eq!(cyclic.to_string(), "pages 1, 2, 3 are ordered in a cycle by the updates:\n  update 1: 1 before 2\n  update 2: 2 before 3\n  update 3: 3 before 1\n");
+
+    let chain = PrintQueue::<u64>::parse("1|2\n2|3\n").unwrap().rules;
+    let closure = PrecedenceClosure::new(&chain);
+    assert!(closure.must_precede(&1, &3));
+    assert!(!closure.must_precede(&3, &1));
+    assert!(!closure.must_precede(&1, &4));
+    // As a whole the real rules are one big cycle, but within each update
+    // the closure orders every pair of pages
+    for page_list in &print_queue.updates {
+        let pages = reorder(page_list, &print_queue.rules).unwrap().pages;
+        let closure = PrecedenceClosure::for_update(&print_queue.rules, &pages);
+        assert!(pages.iter().enumerate().all(|(a, page_a)| pages[a + 1..].iter().all(|page_b| closure.must_precede(page_a, page_b))));
+    }
+    let old_rules = PrintQueue::parse("1|2\n3|4\n4|5\n").unwrap().rules;
+    let new_rules = PrintQueue::parse("1|2\n2|3\n3|4\n").unwrap().rules;
+    let diff = diff_rules(&old_rules, &new_rules, &[vec![3, 2], vec![1, 2, 3, 4], vec![1, 4], vec![2, 1], vec![5, 4]]);
+    assert_eq!(diff.added, [Rule { before: 2, after: 3, line: 2 }]);
+    assert_eq!(diff.removed, [Rule { before: 4, after: 5, line: 3 }]);
+    assert_eq!(diff.now_implied, [(1, 3), (1, 4), (2, 4)]);
+    assert_eq!(diff.changed_updates, [
+        UpdateChange { update: vec![3, 2], was_valid: true },
+        UpdateChange { update: vec![5, 4], was_valid: false },
+    ]);
+    // Pages that are never in the same update aren't ordered by the rules
+    assert!(diff_rules(&old_rules, &new_rules, &[vec![1, 4]]).now_implied.is_empty());
+    // The real rules are cyclic as a whole, so the closures over all of
+    // them imply everything. Within an update, a rule that's dropped but
+    // still follows through a third page is found, as long as the old rules
+    // had no way of ordering that pair
+    let without = |dropped: &dyn Fn(&Rule<u64>) -> bool| {
+        let mut rules = OrderingRules::new();
+        print_queue.rules.rules.iter().filter(|rule| !dropped(rule)).for_each(|rule| rules.add(rule.before, rule.after, rule.line));
+        rules
+    };
+    let update = &print_queue.updates[0];
+    let (a, b) = (update[0], update[2]);
+    let real_new_rules = without(&|rule| (rule.before, rule.after) == (a, b));
+    let real_old_rules = without(&|rule| rule.before == a);
+    assert!(real_new_rules.must_precede(&a, &update[1]) && real_new_rules.must_precede(&update[1], &b));
+    assert_eq!(diff_rules(&real_old_rules, &real_new_rules, std::slice::from_ref(update)).now_implied, [(a, b)]);
+
+    assert_eq!(day5_1(&print_queue), 5108);
+    assert_eq!(day5_2(&print_queue), 7380);
 }
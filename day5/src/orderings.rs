@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use crate::{InconsistentRules, OrderingRules, Page};
+
+// Counting and enumerating every valid order of an update, i.e. the linear
+// extensions of the rules restricted to its pages.
+//
+// Counting is a DP over the sets of pages already placed, memoised on a
+// bitmask. Only sets closed under the rules can be reached, which keeps the
+// number of states small unless the update is barely constrained. In that
+// case the count explodes, so the DP gives up as soon as any partial count
+// exceeds the cap (which bounds the number of visited states as well).
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum OrderingsError<P: Page> {
+    InconsistentRules(InconsistentRules<P>),
+    // Pages are tracked in a 64-bit mask
+    TooManyPages(usize),
+    TooManyOrderings { cap: u64 },
+}
+
+// predecessors[b] has bit a set if page a must come before page b
+fn predecessor_masks<P: Page>(page_list: &[P], ordering_rules: &OrderingRules<P>) -> Result<Vec<u64>, OrderingsError<P>> {
+    ordering_rules.validate_for(page_list).map_err(OrderingsError::InconsistentRules)?;
+    if page_list.len() > 64 {
+        return Err(OrderingsError::TooManyPages(page_list.len()));
+    }
+
+    Ok(page_list.iter()
+        .map(|b| page_list.iter()
+            .enumerate()
+            .filter(|(_, a)| ordering_rules.must_precede(a, b))
+            .fold(0, |mask, (a, _)| mask | 1 << a))
+        .collect())
+}
+
+fn is_available(predecessors: &[u64], placed: u64, index: usize) -> bool {
+    placed & (1 << index) == 0 && predecessors[index] & !placed == 0
+}
+
+struct Counter<'a> {
+    predecessors: &'a [u64],
+    all: u64,
+    cap: u64,
+    counts: HashMap<u64, u64>,
+}
+
+impl Counter<'_> {
+    // The number of ways to order the pages not placed yet, or None once
+    // that exceeds the cap
+    fn count(&mut self, placed: u64) -> Option<u64> {
+        if placed == self.all {
+            return Some(1);
+        }
+        if let Some(&count) = self.counts.get(&placed) {
+            return Some(count);
+        }
+
+        let mut count: u64 = 0;
+        for index in 0..self.predecessors.len() {
+            if is_available(self.predecessors, placed, index) {
+                count = count.checked_add(self.count(placed | 1 << index)?).filter(|&count| count <= self.cap)?;
+            }
+        }
+
+        self.counts.insert(placed, count);
+        Some(count)
+    }
+}
+
+pub fn count_valid_orderings<P: Page>(page_list: &[P], ordering_rules: &OrderingRules<P>, cap: u64) -> Result<u64, OrderingsError<P>> {
+    let predecessors = predecessor_masks(page_list, ordering_rules)?;
+    let mut counter = Counter {
+        predecessors: &predecessors,
+        all: if page_list.len() == 64 { u64::MAX } else { (1 << page_list.len()) - 1 },
+        cap,
+        counts: HashMap::new(),
+    };
+    counter.count(0).ok_or(OrderingsError::TooManyOrderings { cap })
+}
+
+// Yields the valid orders one by one, in lexicographic order of the pages'
+// positions in the update, by backtracking over the available pages
+pub struct ValidOrderings<'a, P: Page> {
+    page_list: &'a [P],
+    predecessors: Vec<u64>,
+    order: Vec<usize>,
+    // For every position in the order, the next page index to try there
+    next_choices: Vec<usize>,
+    placed: u64,
+}
+
+pub fn valid_orderings<'a, P: Page>(page_list: &'a [P], ordering_rules: &OrderingRules<P>) -> Result<ValidOrderings<'a, P>, OrderingsError<P>> {
+    Ok(ValidOrderings {
+        page_list,
+        predecessors: predecessor_masks(page_list, ordering_rules)?,
+        order: Vec::with_capacity(page_list.len()),
+        next_choices: vec![0],
+        placed: 0,
+    })
+}
+
+impl<P: Page> Iterator for ValidOrderings<'_, P> {
+    type Item = Vec<P>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // No pages have exactly one order, the empty one, like they have one
+        // count
+        if self.page_list.is_empty() {
+            return self.next_choices.pop().map(|_| Vec::new());
+        }
+
+        while let Some(&first_choice) = self.next_choices.last() {
+            let choice = (first_choice..self.page_list.len())
+                .find(|&index| is_available(&self.predecessors, self.placed, index));
+
+            let Some(index) = choice else {
+                // Nothing left to try at this position, go back one page
+                self.next_choices.pop();
+                if let Some(index) = self.order.pop() {
+                    self.placed &= !(1 << index);
+                }
+                continue;
+            };
+
+            *self.next_choices.last_mut().unwrap() = index + 1;
+            self.order.push(index);
+            self.placed |= 1 << index;
+            self.next_choices.push(0);
+
+            if self.order.len() == self.page_list.len() {
+                let ordering = self.order.iter().map(|&index| self.page_list[index].clone()).collect();
+                self.next_choices.pop();
+                self.placed &= !(1 << self.order.pop().unwrap());
+                return Some(ordering);
+            }
+        }
+
+        None
+    }
+}
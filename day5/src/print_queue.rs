@@ -0,0 +1,77 @@
+use std::fmt;
+
+use crate::{InconsistentRules, OrderingRules, Page, PageList};
+
+// The whole puzzle input, parsed once: the ordering rules, a blank line,
+// then one update per line. Line endings may be CRLF and lines may carry
+// surrounding whitespace, the blank separator line included.
+
+pub struct PrintQueue<P: Page> {
+    pub rules: OrderingRules<P>,
+    pub updates: Vec<PageList<P>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    // Starting at 1
+    pub line: usize,
+    pub content: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {} is neither a rule nor an update: {:?}", self.line, self.content)
+    }
+}
+
+fn parse_rule<P: Page>(line: &str) -> Option<(P, P)> {
+    let (before, after) = line.split_once('|')?;
+    Some((before.trim().parse().ok()?, after.trim().parse().ok()?))
+}
+
+fn parse_update<P: Page>(line: &str) -> Option<PageList<P>> {
+    line.split(',').map(|page| page.trim().parse().ok()).collect()
+}
+
+impl<P: Page> PrintQueue<P> {
+    pub fn parse(input: &str) -> Result<PrintQueue<P>, ParseError> {
+        let mut print_queue = PrintQueue { rules: OrderingRules::new(), updates: Vec::new() };
+        let mut is_reading_rules = true;
+
+        for (line_index, line) in input.lines().enumerate() {
+            let line = line.trim();
+            let error = || ParseError { line: line_index + 1, content: line.to_string() };
+
+            if line.is_empty() {
+                is_reading_rules = false;
+            } else if is_reading_rules {
+                let (before, after) = parse_rule(line).ok_or_else(error)?;
+                print_queue.rules.add(before, after, line_index + 1);
+            } else {
+                print_queue.updates.push(parse_update(line).ok_or_else(error)?);
+            }
+        }
+
+        Ok(print_queue)
+    }
+
+    // Only the rules that apply to some update need to be free of cycles
+    pub fn validate(&self) -> Result<(), InconsistentRules<P>> {
+        self.updates.iter().try_for_each(|update| self.rules.validate_for(update))
+    }
+}
+
+// Back to the input format, with the rules in their original order
+impl<P: Page + fmt::Display> fmt::Display for PrintQueue<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rule in &self.rules.rules {
+            writeln!(f, "{}|{}", rule.before, rule.after)?;
+        }
+        writeln!(f)?;
+        for update in &self.updates {
+            let pages: Vec<String> = update.iter().map(|page| page.to_string()).collect();
+            writeln!(f, "{}", pages.join(","))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeSet;
+
+use crate::{closure::close_transitively, InconsistentRules, OrderingRules, Page, Rule};
+
+// Explains what is wrong with an update and how to fix it with as few moves
+// as possible.
+//
+// Pages that are not moved keep their relative order, so they must not
+// contain a pair that the rules (transitively, through the other pages of
+// the update) want the other way around. Such inverted pairs form a partial
+// order themselves, and the largest set of pages that can stay is a maximum
+// antichain of it, found with a bipartite matching (Dilworth/König).
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Move<P: Page> {
+    pub page: P,
+    // The page to put it right after, or None to put it first
+    pub after: Option<P>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct OrderCheck<P: Page> {
+    pub violations: Vec<Rule<P>>,
+    pub moves: Vec<Move<P>>,
+}
+
+pub fn check_order<P: Page>(page_list: &[P], ordering_rules: &OrderingRules<P>) -> Result<OrderCheck<P>, InconsistentRules<P>> {
+    ordering_rules.validate_for(page_list)?;
+
+    let violations = find_violations(page_list, ordering_rules);
+
+    if violations.is_empty() {
+        return Ok(OrderCheck { violations, moves: Vec::new() });
+    }
+
+    let precedes = transitive_precedence(page_list, ordering_rules);
+    let inverted = |a: usize, b: usize| a < b && precedes[b][a];
+    let kept = find_maximum_antichain(page_list.len(), inverted);
+    let target_order = order_keeping(page_list.len(), &precedes, &kept);
+
+    let moves = target_order.iter().enumerate()
+        .filter(|(_, index)| !kept[**index])
+        .map(|(position, &index)| Move {
+            page: page_list[index].clone(),
+            after: position.checked_sub(1).map(|previous| page_list[target_order[previous]].clone()),
+        })
+        .collect();
+
+    Ok(OrderCheck { violations, moves })
+}
+
+// Every rule whose pages appear the wrong way around, next to each other or
+// not
+pub fn find_violations<P: Page>(page_list: &[P], ordering_rules: &OrderingRules<P>) -> Vec<Rule<P>> {
+    let mut violations = Vec::new();
+    for (a, page_a) in page_list.iter().enumerate() {
+        for page_b in &page_list[a + 1..] {
+            if let Some(rule) = ordering_rules.rule(page_b, page_a) {
+                violations.push(rule);
+            }
+        }
+    }
+    violations
+}
+
+pub fn apply_moves<P: Page>(page_list: &[P], moves: &[Move<P>]) -> Vec<P> {
+    let mut pages = page_list.to_vec();
+    for page_move in moves {
+        pages.retain(|page| *page != page_move.page);
+        let position = page_move.after.as_ref()
+            .map_or(0, |after| pages.iter().position(|page| page == after).unwrap() + 1);
+        pages.insert(position, page_move.page.clone());
+    }
+    pages
+}
+
+// precedes[a][b] if page a must come before page b, directly or through
+// other pages of the update (Floyd-Warshall)
+fn transitive_precedence<P: Page>(page_list: &[P], ordering_rules: &OrderingRules<P>) -> Vec<Vec<bool>> {
+    let mut precedes: Vec<Vec<bool>> = page_list.iter()
+        .map(|a| page_list.iter().map(|b| ordering_rules.must_precede(a, b)).collect())
+        .collect();
+
+    close_transitively(&mut precedes, |row, via| row[via]);
+    precedes
+}
+
+fn find_maximum_antichain(count: usize, is_less: impl Fn(usize, usize) -> bool) -> Vec<bool> {
+    fn try_augment(left: usize, adjacency: &[Vec<usize>], visited: &mut [bool], matched_left_of: &mut [Option<usize>]) -> bool {
+        for &right in &adjacency[left] {
+            if visited[right] {
+                continue;
+            }
+            visited[right] = true;
+            if matched_left_of[right].is_none_or(|other| try_augment(other, adjacency, visited, matched_left_of)) {
+                matched_left_of[right] = Some(left);
+                return true;
+            }
+        }
+        false
+    }
+
+    let adjacency: Vec<Vec<usize>> = (0..count).map(|a| (0..count).filter(|&b| is_less(a, b)).collect()).collect();
+    let mut matched_left_of = vec![None; count];
+    for left in 0..count {
+        try_augment(left, &adjacency, &mut vec![false; count], &mut matched_left_of);
+    }
+
+    // König: everything reachable from an unmatched left vertex over
+    // alternating paths. Elements whose left copy is reached but whose right
+    // copy isn't are outside the minimum vertex cover on both sides.
+    let mut matched_right_of = vec![None; count];
+    for (right, left) in matched_left_of.iter().enumerate() {
+        if let Some(left) = left {
+            matched_right_of[*left] = Some(right);
+        }
+    }
+    let mut reached_left = vec![false; count];
+    let mut reached_right = vec![false; count];
+    let mut stack: Vec<usize> = (0..count).filter(|&left| matched_right_of[left].is_none()).collect();
+    while let Some(left) = stack.pop() {
+        if reached_left[left] {
+            continue;
+        }
+        reached_left[left] = true;
+        for &right in &adjacency[left] {
+            if !reached_right[right] {
+                reached_right[right] = true;
+                if let Some(next) = matched_left_of[right] {
+                    stack.push(next);
+                }
+            }
+        }
+    }
+
+    (0..count).map(|index| reached_left[index] && !reached_right[index]).collect()
+}
+
+// A topological order of the update in which the kept pages stay in their
+// current order, preferring the current position of each page
+fn order_keeping(count: usize, precedes: &[Vec<bool>], kept: &[bool]) -> Vec<usize> {
+    let kept_indices: Vec<usize> = (0..count).filter(|&index| kept[index]).collect();
+    let must_precede = |a: usize, b: usize| {
+        precedes[a][b] || kept_indices.windows(2).any(|window| window[0] == a && window[1] == b)
+    };
+
+    let mut predecessor_counts: Vec<usize> = (0..count).map(|b| (0..count).filter(|&a| must_precede(a, b)).count()).collect();
+    let mut available: BTreeSet<usize> = (0..count).filter(|&index| predecessor_counts[index] == 0).collect();
+    let mut order = Vec::with_capacity(count);
+
+    while let Some(index) = available.pop_first() {
+        order.push(index);
+        for (successor, predecessor_count) in predecessor_counts.iter_mut().enumerate() {
+            if must_precede(index, successor) {
+                *predecessor_count -= 1;
+                if *predecessor_count == 0 {
+                    available.insert(successor);
+                }
+            }
+        }
+    }
+
+    order
+}
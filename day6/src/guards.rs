@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+
+use crate::{rules::TurnRule, simulator::get_position_ahead, turn, Guard, Map, Position};
+
+// Patrols every guard on the map at once, one tick at a time. Each tick,
+// every guard still on patrol either turns, moves a cell or leaves, like
+// the single guard does, in the order the guards were read from the map.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MeetingPolicy {
+    // Guards turn in front of each other like in front of obstacles
+    Block,
+    // Guards that meet, on a cell or by swapping places, stay where they
+    // were and swap facings instead, so that they turn back the way the
+    // other one came. A guard that walks onto the cell of a guard sent back
+    // meets it there and is sent back too
+    Swap,
+    // Guards that meet, on a cell or by swapping places, stop patrolling
+    Stop,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GuardEnd {
+    LeftMap,
+    Stopped,
+    Loop,
+}
+
+#[derive(Debug)]
+pub struct PatrolReport {
+    pub visited: Vec<HashSet<Position>>,
+    pub combined: HashSet<Position>,
+    pub ends: Vec<GuardEnd>,
+    pub ticks: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum GuardState {
+    Patrolling(Guard),
+    Ended(GuardEnd),
+}
+
+pub fn patrol_all(map: &Map, policy: MeetingPolicy) -> PatrolReport {
+    let map_size = (map.width, map.height);
+    let mut states: Vec<GuardState> = map.guards.iter().cloned().map(GuardState::Patrolling).collect();
+    let mut visited: Vec<HashSet<Position>> = map.guards.iter().map(|guard| HashSet::from([guard.position.clone()])).collect();
+    let mut seen_states = HashSet::new();
+    let mut ticks = 0;
+
+    // Everything is deterministic, so once all guards are back in a state
+    // they were in before, the ones still patrolling do so forever
+    while states.iter().any(|state| matches!(state, GuardState::Patrolling(_))) && seen_states.insert(states.clone()) {
+        let previous = states.clone();
+        let mut claimed: Vec<Position> = Vec::new();
+
+        for state in states.iter_mut() {
+            let GuardState::Patrolling(guard) = state else { continue };
+
+            match get_position_ahead(guard, map_size) {
+                None => *state = GuardState::Ended(GuardEnd::LeftMap),
+                Some(ahead) if map.obstacles.contains(&ahead) => guard.facing = turn(&guard.facing, TurnRule::Right),
+                Some(ahead) if policy == MeetingPolicy::Block && is_occupied(&previous, &claimed, &ahead) => guard.facing = turn(&guard.facing, TurnRule::Right),
+                Some(ahead) => {
+                    claimed.push(ahead.clone());
+                    guard.position = ahead;
+                },
+            }
+        }
+
+        let mut meetings = find_meetings(&previous, &states);
+        // Sending guards back can put them in the way of guards that walked
+        // onto their cells, which then meet them there in turn. Every round
+        // sends back at least one more guard, so this ends
+        while policy == MeetingPolicy::Swap && meetings.iter().any(Option::is_some) {
+            swap_meeting_guards(&previous, &meetings, &mut states);
+            meetings = find_meetings(&previous, &states);
+        }
+        for (index, state) in states.iter().enumerate() {
+            if let GuardState::Patrolling(guard) = state {
+                visited[index].insert(guard.position.clone());
+            }
+        }
+        if policy == MeetingPolicy::Stop {
+            stop_meeting_guards(&meetings, &mut states);
+        }
+        ticks += 1;
+    }
+
+    let ends = states.iter().map(|state| match state {
+        GuardState::Patrolling(_) => GuardEnd::Loop,
+        GuardState::Ended(end) => *end,
+    }).collect();
+    let combined = visited.iter().flatten().cloned().collect();
+
+    PatrolReport { visited, combined, ends, ticks }
+}
+
+fn is_occupied(previous: &[GuardState], claimed: &[Position], position: &Position) -> bool {
+    claimed.contains(position) || previous.iter().any(|state| matches!(state, GuardState::Patrolling(guard) if &guard.position == position))
+}
+
+// For every guard, the first other guard it met this tick, by ending up on
+// the same cell or by swapping places with it
+fn find_meetings(previous: &[GuardState], states: &[GuardState]) -> Vec<Option<usize>> {
+    let positions: Vec<Option<(&Position, &Position)>> = previous.iter().zip(states.iter())
+        .map(|states| match states {
+            (GuardState::Patrolling(before), GuardState::Patrolling(after)) => Some((&before.position, &after.position)),
+            _ => None,
+        })
+        .collect();
+
+    (0..positions.len())
+        .map(|index| (0..positions.len()).find(|&other| other != index && match (positions[index], positions[other]) {
+            (Some((before, after)), Some((other_before, other_after))) => after == other_after || (after == other_before && before == other_after),
+            _ => false,
+        }))
+        .collect()
+}
+
+fn swap_meeting_guards(previous: &[GuardState], meetings: &[Option<usize>], states: &mut [GuardState]) {
+    let met_states = states.to_vec();
+    for (index, met) in meetings.iter().enumerate() {
+        let Some(other) = *met else { continue };
+        let (GuardState::Patrolling(before), GuardState::Patrolling(other_guard)) = (&previous[index], &met_states[other]) else { continue };
+        states[index] = GuardState::Patrolling(Guard { position: before.position.clone(), facing: other_guard.facing });
+    }
+}
+
+fn stop_meeting_guards(meetings: &[Option<usize>], states: &mut [GuardState]) {
+    for (index, met) in meetings.iter().enumerate() {
+        if met.is_some() {
+            states[index] = GuardState::Ended(GuardEnd::Stopped);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect_visited_positions, looping_sample_map, read_map, sample_input};
+
+    fn cells(report: &PatrolReport, guard: usize) -> Vec<usize> {
+        let mut cells: Vec<usize> = report.visited[guard].iter().map(|position| position.x).collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    // A single guard patrols the same way under every meeting policy
+    #[test]
+    fn single_guard() {
+        let sample_map = read_map(sample_input());
+        for policy in [MeetingPolicy::Block, MeetingPolicy::Swap, MeetingPolicy::Stop] {
+            let report = patrol_all(&sample_map, policy);
+            assert_eq!(report.visited, vec![collect_visited_positions(&sample_map)]);
+            assert_eq!(report.ends, vec![GuardEnd::LeftMap]);
+            assert_eq!(patrol_all(&looping_sample_map(), policy).ends, vec![GuardEnd::Loop]);
+        }
+    }
+
+    #[test]
+    fn corridor() {
+        let corridor = read_map("..>...<..");
+        let swapping = patrol_all(&corridor, MeetingPolicy::Swap);
+        // They meet at 4 on the second tick and turn back without stepping on it
+        assert_eq!((cells(&swapping, 0), cells(&swapping, 1)), ((0..4).collect(), (5..9).collect()));
+        assert_eq!((swapping.combined.len(), swapping.ends), (8, vec![GuardEnd::LeftMap, GuardEnd::LeftMap]));
+        let stopping = patrol_all(&corridor, MeetingPolicy::Stop);
+        assert_eq!((cells(&stopping, 0), cells(&stopping, 1)), (vec![2, 3, 4], vec![4, 5, 6]));
+        assert_eq!(stopping.ends, vec![GuardEnd::Stopped, GuardEnd::Stopped]);
+        let blocking = patrol_all(&corridor, MeetingPolicy::Block);
+        assert_eq!((cells(&blocking, 0), cells(&blocking, 1)), (vec![2, 3, 4], vec![5, 6]));
+        assert_eq!((blocking.ends, blocking.ticks), (vec![GuardEnd::LeftMap, GuardEnd::LeftMap], 4));
+    }
+
+    // The first guard walks onto the cell the second is sent back to, so it
+    // is sent back as well, taking the second's new facing
+    #[test]
+    fn swapping_in_a_crowd() {
+        let crowded = patrol_all(&read_map(".>>.<.."), MeetingPolicy::Swap);
+        assert_eq!((cells(&crowded, 0), cells(&crowded, 1), cells(&crowded, 2)), (vec![0, 1], (2..7).collect(), (4..7).collect()));
+        assert_eq!(crowded.ends, vec![GuardEnd::LeftMap; 3]);
+    }
+
+    // A second guard walking down towards the sample's guard, meeting it
+    // between (4, 4) and (4, 5) on the second tick
+    #[test]
+    fn meeting_on_the_sample() {
+        let meeting_map = read_map(&sample_input().replacen("\n..........\n", "\n....v.....\n", 1));
+        // Swapping facings sends it on the sample guard's patrol and the
+        // sample guard off the bottom
+        let swapping = patrol_all(&meeting_map, MeetingPolicy::Swap);
+        assert_eq!((swapping.visited[0].len(), swapping.visited[1].len(), swapping.combined.len()), (40, 5, 42));
+        assert_eq!(swapping.visited[1], (5..10).map(|y| Position { x: 4, y }).collect());
+        let stopping = patrol_all(&meeting_map, MeetingPolicy::Stop);
+        assert_eq!((stopping.combined.len(), stopping.ticks), (5, 2));
+        assert!(stopping.visited.iter().all(|visited| visited.contains(&Position { x: 4, y: 4 })));
+        // Turned away from each other, they leave by the left and right edges
+        let blocking = patrol_all(&meeting_map, MeetingPolicy::Block);
+        assert_eq!((blocking.visited[0].len(), blocking.visited[1].len(), blocking.ticks), (7, 7, 8));
+        assert!(blocking.visited[0].contains(&Position { x: 0, y: 4 }) && blocking.visited[1].contains(&Position { x: 9, y: 5 }));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{is_loop, Facing, FACINGS, Guard, Map, ObstacleLayout, Position};
+
+// Where the guard stops when it sets off from any cell in any direction,
+// precomputed so that each leg of a route is a single lookup.
+//
+// Adding an obstacle only changes the stops of the cells that see it along
+// its row or column, so the table is patched in place instead of rebuilt,
+// and the patch can be reverted to try the next obstacle.
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Stop {
+    position: Position,
+    is_facing_obstacle: bool,
+}
+
+#[derive(Clone)]
+pub struct JumpTable {
+    width: usize,
+    height: usize,
+    is_obstacle: Vec<bool>,
+    stops: Vec<[Stop; 4]>,
+}
+
+// The entries overwritten by `JumpTable::add`, in the order they were
+// written
+pub struct Patch {
+    obstacle: Position,
+    is_new: bool,
+    previous: Vec<(usize, usize, Stop)>,
+}
+
+fn facing_index(facing: &Facing) -> usize {
+    match facing {
+        Facing::Up => 0,
+        Facing::Down => 1,
+        Facing::Left => 2,
+        Facing::Right => 3,
+    }
+}
+
+impl JumpTable {
+    pub fn new(map: &Map) -> JumpTable {
+        let (width, height) = (map.width, map.height);
+        let unset = Stop { position: Position { x: 0, y: 0 }, is_facing_obstacle: false };
+        let mut table = JumpTable {
+            width,
+            height,
+            is_obstacle: vec![false; width * height],
+            stops: vec![[unset.clone(), unset.clone(), unset.clone(), unset]; width * height],
+        };
+
+        for (x, column) in &map.obstacles.vertical {
+            for y in column {
+                table.is_obstacle[y * width + x] = true;
+            }
+        }
+
+        // Sweeping against the facing, the stop only changes after passing
+        // an obstacle
+        for x in 0..width {
+            let mut stop = Stop { position: Position { x, y: 0 }, is_facing_obstacle: false };
+            for y in 0..height {
+                table.set_or_restart(x, y, &Facing::Up, &mut stop);
+            }
+            let mut stop = Stop { position: Position { x, y: height.saturating_sub(1) }, is_facing_obstacle: false };
+            for y in (0..height).rev() {
+                table.set_or_restart(x, y, &Facing::Down, &mut stop);
+            }
+        }
+        for y in 0..height {
+            let mut stop = Stop { position: Position { x: 0, y }, is_facing_obstacle: false };
+            for x in 0..width {
+                table.set_or_restart(x, y, &Facing::Left, &mut stop);
+            }
+            let mut stop = Stop { position: Position { x: width.saturating_sub(1), y }, is_facing_obstacle: false };
+            for x in (0..width).rev() {
+                table.set_or_restart(x, y, &Facing::Right, &mut stop);
+            }
+        }
+
+        table
+    }
+
+    fn set_or_restart(&mut self, x: usize, y: usize, facing: &Facing, stop: &mut Stop) {
+        if self.is_obstacle[y * self.width + x] {
+            // An obstacle on the edge the guard walks away from has no cell
+            // behind it to stop in, and no cells whose stop it changes
+            if let Some((x, y)) = self.cell_behind(x, y, facing) {
+                *stop = Stop { position: Position { x, y }, is_facing_obstacle: true };
+            }
+        } else {
+            self.stops[y * self.width + x][facing_index(facing)] = stop.clone();
+        }
+    }
+
+    // The next cell against the facing, i.e. the one the guard would walk
+    // from to reach (x, y)
+    fn cell_behind(&self, x: usize, y: usize, facing: &Facing) -> Option<(usize, usize)> {
+        match facing {
+            Facing::Up => Some((x, y + 1)).filter(|&(_, y)| y < self.height),
+            Facing::Down => Some((x, y.checked_sub(1)?)),
+            Facing::Left => Some((x + 1, y)).filter(|&(x, _)| x < self.width),
+            Facing::Right => Some((x.checked_sub(1)?, y)),
+        }
+    }
+
+    pub fn add(&mut self, obstacle: &Position) -> Patch {
+        let index = obstacle.y * self.width + obstacle.x;
+        let mut patch = Patch { obstacle: obstacle.clone(), is_new: !self.is_obstacle[index], previous: Vec::new() };
+        if !patch.is_new {
+            return patch;
+        }
+        self.is_obstacle[index] = true;
+
+        // Walking away from the obstacle, every free cell up to the next
+        // obstacle (or the edge) now stops in front of it when facing it
+        for facing in FACINGS {
+            let mut stop = Stop { position: obstacle.clone(), is_facing_obstacle: false };
+            self.set_or_restart(obstacle.x, obstacle.y, &facing, &mut stop);
+
+            let mut cell = self.cell_behind(obstacle.x, obstacle.y, &facing);
+            while let Some((x, y)) = cell {
+                if self.is_obstacle[y * self.width + x] {
+                    break;
+                }
+                let entry = &mut self.stops[y * self.width + x][facing_index(&facing)];
+                patch.previous.push((y * self.width + x, facing_index(&facing), std::mem::replace(entry, stop.clone())));
+                cell = self.cell_behind(x, y, &facing);
+            }
+        }
+
+        patch
+    }
+
+    pub fn revert(&mut self, patch: Patch) {
+        if !patch.is_new {
+            return;
+        }
+        self.is_obstacle[patch.obstacle.y * self.width + patch.obstacle.x] = false;
+        for (cell, facing, stop) in patch.previous.into_iter().rev() {
+            self.stops[cell][facing] = stop;
+        }
+    }
+}
+
+impl ObstacleLayout for JumpTable {
+    fn find_position_before_next_obstacle(&self, guard: &Guard, _map_size: (usize, usize)) -> (Position, bool) {
+        let stop = &self.stops[guard.position.y * self.width + guard.position.x][facing_index(&guard.facing)];
+        (stop.position.clone(), stop.is_facing_obstacle)
+    }
+}
+
+// Tries every candidate by patching a single table and reverting it again
+pub fn find_looping_obstructions(map: &Map, candidates: &[Position]) -> Vec<Position> {
+    let guard = map.guard_start().expect("Expected exactly one guard");
+    let mut table = JumpTable::new(map);
+
+    candidates.iter()
+        .filter(|&candidate| {
+            let patch = table.add(candidate);
+            let is_looping = is_loop(guard.clone(), &table, (map.width, map.height));
+            table.revert(patch);
+            is_looping
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect_obstruction_candidates, find_position_before_next_obstacle, input, read_map, sample_input};
+
+    // The jump table stops where the sorted obstacle lists do, from every
+    // cell in every direction, also after patching in obstacles
+    #[test]
+    fn stops_like_obstacle_lists() {
+        for map in [read_map(sample_input()), read_map(input())] {
+            let size = (map.width, map.height);
+            let mut obstacles = map.obstacles.clone();
+            let mut table = JumpTable::new(&map);
+            let added = [Position { x: 3, y: 6 }, Position { x: 0, y: 0 }, Position { x: size.0 - 1, y: 5 }, Position { x: 5, y: size.1 - 1 }];
+            let patches: Vec<_> = added.iter().map(|obstacle| {
+                obstacles.add(obstacle);
+                table.add(obstacle)
+            }).collect();
+            for y in 0..size.1 {
+                for x in 0..size.0 {
+                    for facing in FACINGS {
+                        let guard = Guard { position: Position { x, y }, facing };
+                        if !obstacles.contains(&guard.position) {
+                            assert_eq!(table.find_position_before_next_obstacle(&guard, size), find_position_before_next_obstacle(&guard, &obstacles, size));
+                        }
+                    }
+                }
+            }
+            patches.into_iter().rev().for_each(|patch| table.revert(patch));
+            let guard = map.guard_start().unwrap();
+            for facing in FACINGS {
+                let guard = Guard { facing, ..guard.clone() };
+                assert_eq!(table.find_position_before_next_obstacle(&guard, size), find_position_before_next_obstacle(&guard, &map.obstacles, size));
+            }
+        }
+    }
+
+    #[test]
+    fn looping_obstructions() {
+        let sample_map = read_map(sample_input());
+        assert_eq!(find_looping_obstructions(&sample_map, &collect_obstruction_candidates(&sample_map)).len(), 6);
+        let map = read_map(input());
+        assert_eq!(find_looping_obstructions(&map, &collect_obstruction_candidates(&map)).len(), 1523);
+    }
+}
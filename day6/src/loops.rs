@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use crate::{resume, route, Guard, Map, ObstacleLayout, Position, Waypoint};
+
+// The loop a guard ends up patrolling forever, as found by `route`.
+//
+// Every waypoint of a loop is in front of an obstacle, so the guard turns
+// at each of them and walks straight from one to the next, and the corners
+// of the loop are its waypoints.
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct Bounds {
+    pub min: Position,
+    pub max: Position,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct Loop {
+    // Starting with the first waypoint the guard reaches that's part of the
+    // loop
+    pub waypoints: Vec<Waypoint>,
+    // Cells moved and turns taken to go round once
+    pub period: usize,
+    pub bounds: Bounds,
+}
+
+impl Loop {
+    pub fn entry(&self) -> &Waypoint {
+        &self.waypoints[0]
+    }
+
+    fn new(waypoints: Vec<Waypoint>) -> Loop {
+        let distance = |from: &Position, to: &Position| from.x.abs_diff(to.x) + from.y.abs_diff(to.y);
+        let moves: usize = waypoints.iter()
+            .zip(waypoints.iter().cycle().skip(1))
+            .map(|(from, to)| distance(&from.position, &to.position))
+            .sum();
+
+        let bounds = Bounds {
+            min: Position {
+                x: waypoints.iter().map(|waypoint| waypoint.position.x).min().unwrap(),
+                y: waypoints.iter().map(|waypoint| waypoint.position.y).min().unwrap(),
+            },
+            max: Position {
+                x: waypoints.iter().map(|waypoint| waypoint.position.x).max().unwrap(),
+                y: waypoints.iter().map(|waypoint| waypoint.position.y).max().unwrap(),
+            },
+        };
+
+        Loop { period: moves + waypoints.len(), waypoints, bounds }
+    }
+}
+
+pub fn find_loop<O: ObstacleLayout>(guard: Guard, obstacles: &O, map_size: (usize, usize)) -> Option<Loop> {
+    let mut waypoints = Vec::new();
+    let mut indices = HashMap::new();
+    let mut entry = None;
+
+    route(guard, obstacles, map_size, |waypoint| {
+        if let Some(&index) = indices.get(&waypoint) {
+            entry = Some(index);
+            return false;
+        }
+        indices.insert(waypoint.clone(), waypoints.len());
+        waypoints.push(waypoint);
+        true
+    });
+
+    entry.map(|entry| Loop::new(waypoints.split_off(entry)))
+}
+
+// Every obstruction that makes the guard loop, with the loop it ends up in.
+// The loop is followed from the guard's start rather than from where the
+// search resumes, as that decides which of its waypoints is the entry
+pub fn find_induced_loops(map: &Map) -> Vec<(Position, Loop)> {
+    let guard = map.guard_start().expect("Expected exactly one guard");
+    resume::search(map, |_, overlay, map_size| find_loop(guard.clone(), overlay, map_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{input, looping_sample_map, read_map, sample_input, simulator::record_trace, Facing};
+
+    // The loop of the first looping obstruction is the rectangle drawn in
+    // the puzzle text. The guard starts on it, turning up at (4, 6), so the
+    // recorded trace is exactly one round
+    #[test]
+    fn sample_loop() {
+        let looping_map = looping_sample_map();
+        let map_size = (looping_map.width, looping_map.height);
+        let guard = looping_map.guard_start().unwrap();
+        let sample_loop = find_loop(guard.clone(), &looping_map.obstacles, map_size).unwrap();
+        let corners: Vec<(usize, usize, Facing)> = sample_loop.waypoints.iter().map(|waypoint| (waypoint.position.x, waypoint.position.y, waypoint.facing)).collect();
+        assert_eq!(corners, vec![(4, 1, Facing::Up), (8, 1, Facing::Right), (8, 6, Facing::Down), (4, 6, Facing::Left)]);
+        assert_eq!(sample_loop.entry(), &sample_loop.waypoints[0]);
+        assert_eq!(sample_loop.period, 22);
+        assert_eq!(sample_loop.period, record_trace(guard.clone(), &looping_map.obstacles, map_size).states.len());
+        assert_eq!(sample_loop.bounds, Bounds { min: Position { x: 4, y: 1 }, max: Position { x: 8, y: 6 } });
+
+        let sample_map = read_map(sample_input());
+        assert_eq!(find_loop(guard, &sample_map.obstacles, map_size), None);
+        let sample_loops = find_induced_loops(&sample_map);
+        assert_eq!(sample_loops.len(), 6);
+        assert!(sample_loops.contains(&(Position { x: 3, y: 6 }, sample_loop)));
+    }
+
+    #[test]
+    fn induced_loops() {
+        assert_eq!(find_induced_loops(&read_map(input())).len(), 1523);
+    }
+}
@@ -1,10 +1,39 @@
 use core::panic;
-use std::{cmp::{min, max}, collections::{HashMap, HashSet}};
+use std::{cmp::{min, max}, collections::{HashMap, HashSet}, env, io, time::Duration};
+
+use parallel::{available_threads, benchmark, count_looping_obstructions};
+use render::animate;
+use rules::{MovementRules, RouteError, TurnRule};
+use simulator::{collect_visited_positions_with_rules, record_trace, walk_with_rules, TraceEnd};
+
+// The extensions are only exercised by the unit tests, leaving much of them
+// unused by the puzzle answers
+#[cfg_attr(not(test), allow(dead_code))]
+mod guards;
+#[cfg_attr(not(test), allow(dead_code))]
+mod jump_table;
+#[cfg_attr(not(test), allow(dead_code))]
+mod loops;
+mod parallel;
+#[cfg_attr(not(test), allow(dead_code))]
+mod placement;
+#[cfg_attr(not(test), allow(dead_code))]
+mod render;
+#[cfg_attr(not(test), allow(dead_code))]
+mod resume;
+#[cfg_attr(not(test), allow(dead_code))]
+mod rules;
+#[cfg_attr(not(test), allow(dead_code))]
+mod simulator;
 
 fn input() -> &'static str {
     include_str!("../inputs/1.txt")
 }
 
+fn sample_input() -> &'static str {
+    include_str!("../inputs/sample.txt")
+}
+
 type ObstacleMap = HashMap<usize, Vec<usize>>;
 
 #[derive(Clone)]
@@ -22,12 +51,22 @@ struct Position {
 impl Obstacles {
     fn add(&mut self, obstacle: &Position) {
         let vertical = self.vertical.entry(obstacle.x).or_default();
-        vertical.push(obstacle.y);
-        vertical.sort_unstable();
+        if let Err(index) = vertical.binary_search(&obstacle.y) {
+            vertical.insert(index, obstacle.y);
+        }
 
         let horizontal = self.horizontal.entry(obstacle.y).or_default();
-        horizontal.push(obstacle.x);
-        horizontal.sort_unstable();
+        if let Err(index) = horizontal.binary_search(&obstacle.x) {
+            horizontal.insert(index, obstacle.x);
+        }
+    }
+
+    fn contains(&self, position: &Position) -> bool {
+        self.vertical.get(&position.x).is_some_and(|column| column.binary_search(&position.y).is_ok())
+    }
+
+    fn count(&self) -> usize {
+        self.vertical.values().map(Vec::len).sum()
     }
 }
 
@@ -39,7 +78,9 @@ enum Facing {
     Right,
 }
 
-#[derive(Clone)]
+const FACINGS: [Facing; 4] = [Facing::Up, Facing::Down, Facing::Left, Facing::Right];
+
+#[derive(Hash, Eq, PartialEq, Clone, Debug)]
 struct Guard {
     position: Position,
     facing: Facing,
@@ -50,7 +91,18 @@ struct Map {
     width: usize,
     height: usize,
     obstacles: Obstacles,
-    guard_start: Option<Guard>,
+    guards: Vec<Guard>,
+}
+
+impl Map {
+    // The puzzle's maps have a single guard. Maps with none or several have
+    // no single start to walk from, only `patrol_all` walks those
+    fn guard_start(&self) -> Option<Guard> {
+        match self.guards.as_slice() {
+            [guard] => Some(guard.clone()),
+            _ => None,
+        }
+    }
 }
 
 fn get_guard_facing_from_char(character: char) -> Facing {
@@ -63,15 +115,24 @@ fn get_guard_facing_from_char(character: char) -> Facing {
     }
 }
 
-fn read_map() -> Map {
+fn get_char_from_guard_facing(facing: &Facing) -> char {
+    match facing {
+        Facing::Up => '^',
+        Facing::Down => 'v',
+        Facing::Left => '<',
+        Facing::Right => '>',
+    }
+}
+
+fn read_map(input: &str) -> Map {
     let mut map = Map {
         width: 0,
         height: 0,
         obstacles: Obstacles { vertical: HashMap::new(), horizontal: HashMap::new() },
-        guard_start: None,
+        guards: Vec::new(),
     };
 
-    input().lines()
+    input.lines()
         .enumerate()
         .for_each(|(row, line)| {
             map.height = max(map.height, row + 1);
@@ -83,8 +144,7 @@ fn read_map() -> Map {
                             map.obstacles.add(&Position {x: column, y: row});
                         },
                         '^' | '>' | 'v' | '<' => {
-                            assert!(map.guard_start.is_none(), "Multiple guards found");
-                            map.guard_start = Some(Guard { position: Position { x: column, y: row }, facing: get_guard_facing_from_char(character) });
+                            map.guards.push(Guard { position: Position { x: column, y: row }, facing: get_guard_facing_from_char(character) });
                         },
                         _ => {},
                     }
@@ -94,19 +154,26 @@ fn read_map() -> Map {
     map
 }
 
-fn get_position_before_position(pos: usize, facing: &Facing) -> usize {
+// The coordinate the guard stops at when walking into an obstacle at `pos`,
+// which doesn't exist when the obstacle is on the edge it walks away from
+fn get_position_before_position(pos: usize, facing: &Facing) -> Option<usize> {
     match facing {
-        Facing::Up | Facing::Left => pos + 1,
-        Facing::Down | Facing::Right => pos - 1,
+        Facing::Up | Facing::Left => pos.checked_add(1),
+        Facing::Down | Facing::Right => pos.checked_sub(1),
     }
 }
 
-fn turn(facing: &Facing) -> Facing {
-    match facing {
+fn turn(facing: &Facing, rule: TurnRule) -> Facing {
+    let turn_right = |facing: &Facing| match facing {
         Facing::Up => Facing::Right,
         Facing::Right => Facing::Down,
         Facing::Down => Facing::Left,
         Facing::Left => Facing::Up,
+    };
+    match rule {
+        TurnRule::Right => turn_right(facing),
+        TurnRule::Reverse => turn_right(&turn_right(facing)),
+        TurnRule::Left => turn_right(&turn_right(&turn_right(facing))),
     }
 }
 
@@ -116,37 +183,81 @@ fn find_position_before_next_obstacle(guard: &Guard, obstacles: &Obstacles, map_
             obstacles.vertical.get(&guard.position.x)
                 .and_then(|column|
                     column.iter().rfind(|&y| y < &guard.position.y ))
-                .map(|y|
-                    (Position { x: guard.position.x, y: get_position_before_position(*y, &guard.facing) }, true))
+                .and_then(|y| get_position_before_position(*y, &guard.facing))
+                .map(|y| (Position { x: guard.position.x, y }, true))
                 .unwrap_or((Position { x: guard.position.x, y: 0 }, false))
         },
         Facing::Down => {
             obstacles.vertical.get(&guard.position.x)
                 .and_then(|column|
                     column.iter().find(|&y| y > &guard.position.y ))
-                .map(|y|
-                    (Position { x: guard.position.x, y: get_position_before_position(*y, &guard.facing) }, true))
-                .unwrap_or((Position { x: guard.position.x, y: map_size.1 - 1 }, false))
+                .and_then(|y| get_position_before_position(*y, &guard.facing))
+                .map(|y| (Position { x: guard.position.x, y }, true))
+                .unwrap_or((Position { x: guard.position.x, y: map_size.1.saturating_sub(1) }, false))
         },
         Facing::Left => {
             obstacles.horizontal.get(&guard.position.y)
                 .and_then(|row|
                     row.iter().rfind(|&x| x < &guard.position.x ))
-                .map(|x|
-                    (Position { x: get_position_before_position(*x, &guard.facing), y: guard.position.y }, true))
+                .and_then(|x| get_position_before_position(*x, &guard.facing))
+                .map(|x| (Position { x, y: guard.position.y }, true))
                 .unwrap_or((Position { x: 0, y: guard.position.y }, false))
         },
         Facing::Right => {
             obstacles.horizontal.get(&guard.position.y)
                 .and_then(|row|
                     row.iter().find(|&x| x > &guard.position.x ))
-                .map(|x|
-                    (Position { x: get_position_before_position(*x, &guard.facing), y: guard.position.y }, true))
-                .unwrap_or((Position { x: map_size.0 - 1, y: guard.position.y }, false))
+                .and_then(|x| get_position_before_position(*x, &guard.facing))
+                .map(|x| (Position { x, y: guard.position.y }, true))
+                .unwrap_or((Position { x: map_size.0.saturating_sub(1), y: guard.position.y }, false))
         },
     }
 }
 
+// Anything the guard can bump into, so that routes can be walked around
+// obstacles that aren't all stored in one `Obstacles`
+trait ObstacleLayout {
+    fn find_position_before_next_obstacle(&self, guard: &Guard, map_size: (usize, usize)) -> (Position, bool);
+}
+
+impl ObstacleLayout for Obstacles {
+    fn find_position_before_next_obstacle(&self, guard: &Guard, map_size: (usize, usize)) -> (Position, bool) {
+        find_position_before_next_obstacle(guard, self, map_size)
+    }
+}
+
+// One extra obstacle on top of shared obstacles, without copying them
+struct ObstacleOverlay<'a, O: ObstacleLayout = Obstacles> {
+    base: &'a O,
+    extra: Position,
+}
+
+impl<O: ObstacleLayout> ObstacleLayout for ObstacleOverlay<'_, O> {
+    fn find_position_before_next_obstacle(&self, guard: &Guard, map_size: (usize, usize)) -> (Position, bool) {
+        let (stop, is_facing_obstacle) = self.base.find_position_before_next_obstacle(guard, map_size);
+        let (position, extra) = (&guard.position, &self.extra);
+
+        // The extra obstacle only matters if it's on the segment the guard
+        // would otherwise walk, in which case it stops the guard first
+        let blocking = match guard.facing {
+            Facing::Up => extra.x == position.x && extra.y < position.y && extra.y >= stop.y,
+            Facing::Down => extra.x == position.x && extra.y > position.y && extra.y <= stop.y,
+            Facing::Left => extra.y == position.y && extra.x < position.x && extra.x >= stop.x,
+            Facing::Right => extra.y == position.y && extra.x > position.x && extra.x <= stop.x,
+        };
+
+        let before_extra = match guard.facing {
+            Facing::Up | Facing::Down => get_position_before_position(extra.y, &guard.facing).map(|y| Position { x: extra.x, y }),
+            Facing::Left | Facing::Right => get_position_before_position(extra.x, &guard.facing).map(|x| Position { x, y: extra.y }),
+        };
+
+        match before_extra {
+            Some(before_extra) if blocking => (before_extra, true),
+            _ => (stop, is_facing_obstacle),
+        }
+    }
+}
+
 fn range_between(start: usize, end: usize) -> std::ops::RangeInclusive<usize> {
     min(start, end)..=max(start, end)
 }
@@ -168,16 +279,31 @@ fn get_positions_between(start: &Position, end: &Position) -> Vec<Position> {
     positions
 }
 
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Hash, Eq, PartialEq, Clone, Debug)]
 struct Waypoint {
     position: Position,
     facing: Facing,
 }
 
-fn route<F>(mut guard: Guard, obstacles: &Obstacles, map_size: (usize, usize), mut callback: F)
-where F: FnMut(Waypoint) -> bool {
+fn route<O, F>(guard: Guard, obstacles: &O, map_size: (usize, usize), callback: F)
+where O: ObstacleLayout, F: FnMut(Waypoint) -> bool {
+    route_segments(guard, obstacles, map_size, TurnRule::Right, callback)
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn route_with_rules<O, F>(guard: Guard, obstacles: &O, map_size: (usize, usize), rules: &MovementRules, callback: F) -> Result<(), RouteError>
+where O: ObstacleLayout, F: FnMut(Waypoint) -> bool {
+    if !rules.walks_in_segments() {
+        return Err(RouteError::NotInSegments);
+    }
+    route_segments(guard, obstacles, map_size, rules.turn, callback);
+    Ok(())
+}
+
+fn route_segments<O, F>(mut guard: Guard, obstacles: &O, map_size: (usize, usize), turn_rule: TurnRule, mut callback: F)
+where O: ObstacleLayout, F: FnMut(Waypoint) -> bool {
     loop {
-        let (next_position, is_facing_obstacle) = find_position_before_next_obstacle(&guard, obstacles, map_size);
+        let (next_position, is_facing_obstacle) = obstacles.find_position_before_next_obstacle(&guard, map_size);
         guard.position = next_position;
 
         if !callback(Waypoint { position: guard.position.clone(), facing: guard.facing }) {
@@ -188,15 +314,17 @@ where F: FnMut(Waypoint) -> bool {
             return
         }
 
-        guard.facing = turn(&guard.facing);
+        guard.facing = turn(&guard.facing, turn_rule);
     }
 }
 
 fn collect_visited_positions(map: &Map) -> HashSet<Position> {
-    let mut previous_position = map.guard_start.clone().unwrap().position;
-    let mut visited_positions = HashSet::new();
+    let mut previous_position = map.guard_start().expect("Expected exactly one guard").position;
+    // Segments of no length, like when the guard starts facing the edge of
+    // the map, don't include their cell
+    let mut visited_positions = HashSet::from([previous_position.clone()]);
 
-    route(map.guard_start.clone().unwrap(), &map.obstacles, (map.width, map.height), |waypoint| {
+    route(map.guard_start().expect("Expected exactly one guard"), &map.obstacles, (map.width, map.height), |waypoint| {
         for position in get_positions_between(&previous_position, &waypoint.position) {
             visited_positions.insert(position);
         }
@@ -207,45 +335,248 @@ fn collect_visited_positions(map: &Map) -> HashSet<Position> {
     visited_positions
 }
 
+fn is_loop<O: ObstacleLayout>(guard: Guard, obstacles: &O, map_size: (usize, usize)) -> bool {
+    let mut visited_waypoints = HashSet::new();
+
+    let mut is_loop = false;
+    route(guard, obstacles, map_size, |waypoint| {
+        let is_new_waypoint = visited_waypoints.insert(waypoint);
+        is_loop |= !is_new_waypoint;
+        is_new_waypoint
+    });
+
+    is_loop
+}
+
+// Rules that can't be routed from turn to turn are walked cell by cell
+#[cfg_attr(not(test), allow(dead_code))]
+fn is_loop_with_rules(guard: Guard, obstacles: &Obstacles, map_size: (usize, usize), rules: &MovementRules) -> bool {
+    let mut visited_waypoints = HashSet::new();
+
+    let mut is_loop = false;
+    let routed = route_with_rules(guard.clone(), obstacles, map_size, rules, |waypoint| {
+        let is_new_waypoint = visited_waypoints.insert(waypoint);
+        is_loop |= !is_new_waypoint;
+        is_new_waypoint
+    });
+
+    match routed {
+        Ok(()) => is_loop,
+        Err(RouteError::NotInSegments) => walk_with_rules(guard, obstacles, map_size, *rules, |_| {}) == TraceEnd::Loop,
+    }
+}
+
 fn day6_1() -> usize {
-    let map = read_map();
+    let map = read_map(input());
 
     collect_visited_positions(&map).len()
 }
 
-fn day6_2() -> usize {
-    let map = read_map();
-    
-    // The guard will only encounter additional obstacles if they are placed
-    // along its route, so we only need to check the visited positions
-    // let visited_positions = collect_visited_positions(&map);
-    let visited_positions = collect_visited_positions(&map);
-    
+// The guard will only encounter additional obstacles if they are placed
+// along its route, so we only need to check the visited positions. The
+// guard's own starting position can't be obstructed, though
+fn collect_obstruction_candidates(map: &Map) -> Vec<Position> {
+    let guard_start = map.guard_start().expect("Expected exactly one guard");
+    collect_visited_positions(map).into_iter().filter(|position| *position != guard_start.position).collect()
+}
+
+fn find_looping_obstacles(map: &Map) -> Vec<Position> {
     let mut looping_obstacles = Vec::new();
 
-    visited_positions.iter().for_each(| position| {
+    collect_obstruction_candidates(map).into_iter().for_each(|position| {
         let mut map_copy = map.clone();
 
-        map_copy.obstacles.add(position);
-
-        let mut visited_waypoints = HashSet::new();
+        map_copy.obstacles.add(&position);
 
-        let mut is_loop = false;
-        route(map_copy.guard_start.unwrap(), &map_copy.obstacles, (map_copy.width, map_copy.height), |waypoint| {
-            let is_new_waypoint = visited_waypoints.insert(waypoint);
-            is_loop |= !is_new_waypoint;
-            is_new_waypoint
-        });
-
-        if is_loop {
+        if is_loop(map_copy.guard_start().expect("Expected exactly one guard"), &map_copy.obstacles, (map_copy.width, map_copy.height)) {
             looping_obstacles.push(position);
         }
     });
 
-    looping_obstacles.len()
+    looping_obstacles
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn find_looping_obstacles_with_rules(map: &Map, rules: &MovementRules) -> Vec<Position> {
+    let guard_start = map.guard_start().expect("Expected exactly one guard");
+    let (visited_positions, _) = collect_visited_positions_with_rules(map, *rules);
+
+    visited_positions.into_iter().filter(|position| *position != guard_start.position).filter(|position| {
+        let mut map_copy = map.clone();
+        map_copy.obstacles.add(position);
+        is_loop_with_rules(guard_start.clone(), &map_copy.obstacles, (map.width, map.height), rules)
+    }).collect()
+}
+
+fn day6_2() -> usize {
+    let map = read_map(input());
+
+    find_looping_obstacles(&map).len()
+}
+
+// Timings of the ways of solving part 2, only run when asked for with
+// `--bench`
+fn benchmark_part_2(map: &Map) {
+    let candidates = collect_obstruction_candidates(map);
+    let (_, sequential) = benchmark(3, || find_looping_obstacles(map).len());
+    let (_, parallel) = benchmark(3, || count_looping_obstructions(map, available_threads()));
+    let (_, patched) = benchmark(3, || jump_table::find_looping_obstructions(map, &candidates).len());
+    let (_, resumed) = benchmark(3, || resume::find_looping_obstructions(map).len());
+    eprintln!("Part 2: {sequential:?} sequentially, {parallel:?} on {} threads, {patched:?} patching a jump table, {resumed:?} resuming along the route", available_threads());
+}
+
+// Steps the sample's guard through its patrol in the terminal, only run when
+// asked for with `--animate <milliseconds per frame>`
+fn animate_sample(frame_delay: Duration) {
+    let map = read_map(sample_input());
+    let trace = record_trace(map.guard_start().unwrap(), &map.obstacles, (map.width, map.height));
+    animate(&map, None, &trace.states, frame_delay, &mut io::stdout()).unwrap();
 }
 
 fn main() {
     assert_eq!(day6_1(), 5145);
     assert_eq!(day6_2(), 1523);
+
+    let arguments: Vec<String> = env::args().collect();
+    if arguments.iter().any(|argument| argument == "--bench") {
+        benchmark_part_2(&read_map(input()));
+    }
+    if let Some(index) = arguments.iter().position(|argument| argument == "--animate") {
+        let frame_delay = arguments.get(index + 1).and_then(|milliseconds| milliseconds.parse().ok()).expect("Expected --animate <milliseconds>");
+        animate_sample(Duration::from_millis(frame_delay));
+    }
+}
+
+// The sample with the first of its looping obstructions, at (3, 6)
+#[cfg(test)]
+fn looping_sample_map() -> Map {
+    let mut map = read_map(sample_input());
+    map.obstacles.add(&Position { x: 3, y: 6 });
+    map
+}
+
+#[cfg(test)]
+fn sorted(mut positions: Vec<Position>) -> Vec<Position> {
+    positions.sort_unstable_by_key(|position| (position.y, position.x));
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{guards::{patrol_all, MeetingPolicy}, jump_table::JumpTable, loops::find_loop, render::render, rules::{Directions, EdgeRule}, simulator::{collect_visited_positions_with_rules, get_position_ahead, record_trace, TraceEnd}};
+
+    fn draw_map(map_size: (usize, usize), guard: &Guard, obstacles: &[Position]) -> String {
+        (0..map_size.1)
+            .map(|y| (0..map_size.0)
+                .map(|x| match (Position { x, y }) {
+                    position if position == guard.position => get_char_from_guard_facing(&guard.facing),
+                    position if obstacles.contains(&position) => '#',
+                    _ => '.',
+                })
+                .collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Every way of walking a small map agrees for guards on every border cell
+    // facing every direction, with nothing around them, with an obstacle right
+    // in front of them, and boxed in by obstacles on all the other border cells
+    #[test]
+    fn border_guards() {
+        for map_size in [(1, 1), (1, 3), (3, 1), (4, 3)] {
+            let cells: Vec<Position> = (0..map_size.1).flat_map(|y| (0..map_size.0).map(move |x| Position { x, y })).collect();
+            let border: Vec<Position> = cells.iter().filter(|cell| cell.x == 0 || cell.y == 0 || cell.x + 1 == map_size.0 || cell.y + 1 == map_size.1).cloned().collect();
+
+            for position in &border {
+                for facing in FACINGS {
+                    let guard = Guard { position: position.clone(), facing };
+                    let ahead = get_position_ahead(&guard, map_size);
+                    let others: Vec<Position> = border.iter().filter(|cell| *cell != position).cloned().collect();
+                    let layouts = [Vec::new(), ahead.iter().cloned().collect(), others];
+
+                    for obstacles in layouts {
+                        let map = read_map(&draw_map(map_size, &guard, &obstacles));
+                        assert_eq!((map.width, map.height), map_size);
+                        assert_eq!(map.guard_start(), Some(guard.clone()));
+
+                        let trace = record_trace(guard.clone(), &map.obstacles, map_size);
+                        let traced: HashSet<Position> = trace.states.iter().map(|state| state.position.clone()).collect();
+                        assert_eq!(collect_visited_positions_with_rules(&map, MovementRules::default()).0, traced);
+                        assert_eq!(find_loop(guard.clone(), &map.obstacles, map_size).is_some(), trace.end == TraceEnd::Loop);
+
+                        if obstacles.is_empty() {
+                            let to_edge = match facing {
+                                Facing::Up => position.y + 1,
+                                Facing::Down => map_size.1 - position.y,
+                                Facing::Left => position.x + 1,
+                                Facing::Right => map_size.0 - position.x,
+                            };
+                            assert_eq!(traced.len(), to_edge);
+                        }
+                        if trace.end == TraceEnd::LeftMap {
+                            assert_eq!(collect_visited_positions(&map), traced);
+                            assert_eq!(sorted(resume::find_looping_obstructions(&map)), sorted(find_looping_obstacles(&map)));
+                        }
+
+                        let table = JumpTable::new(&map);
+                        for cell in cells.iter().filter(|cell| !map.obstacles.contains(cell)) {
+                            for facing in FACINGS {
+                                let guard = Guard { position: cell.clone(), facing };
+                                assert_eq!(table.find_position_before_next_obstacle(&guard, map_size), find_position_before_next_obstacle(&guard, &map.obstacles, map_size));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Nothing to walk on an empty map, but nothing panics either
+    #[test]
+    fn empty_map() {
+        let empty = read_map("");
+        assert_eq!((empty.width, empty.height, empty.guard_start()), (0, 0, None));
+        JumpTable::new(&empty);
+        assert_eq!(render(&empty, None, &[], None), "");
+        assert!(patrol_all(&empty, MeetingPolicy::Block).visited.is_empty());
+    }
+
+    #[test]
+    fn several_guards_have_no_single_start() {
+        let map = read_map(&sample_input().replacen("\n..........\n", "\n....v.....\n", 1));
+        assert_eq!(map.guards.len(), 2);
+        assert_eq!(map.guard_start(), None);
+    }
+
+    // Diagonal and wrapping rules can't be routed, so loops under them are
+    // found by walking cell by cell instead. Without obstructions the sample's
+    // guard loops when wrapping, and no obstruction traps it diagonally
+    #[test]
+    fn unroutable_rules_are_walked() {
+        let sample_map = read_map(sample_input());
+        let sample_size = (sample_map.width, sample_map.height);
+        let sample_guard = sample_map.guard_start().unwrap();
+        let diagonal_rules = MovementRules { directions: Directions::Eight, ..MovementRules::default() };
+        let wrapping_rules = MovementRules { edge: EdgeRule::Wrap, ..MovementRules::default() };
+
+        for rules in [diagonal_rules, wrapping_rules] {
+            assert_eq!(route_with_rules(sample_guard.clone(), &sample_map.obstacles, sample_size, &rules, |_| true), Err(RouteError::NotInSegments));
+        }
+        assert!(is_loop_with_rules(sample_guard.clone(), &sample_map.obstacles, sample_size, &wrapping_rules));
+        assert!(!is_loop_with_rules(sample_guard.clone(), &sample_map.obstacles, sample_size, &diagonal_rules));
+        assert!(find_looping_obstacles_with_rules(&sample_map, &diagonal_rules).is_empty());
+    }
+
+    // Stopping at the edge finds the same looping obstructions as leaving
+    #[test]
+    fn looping_obstacles_with_edge_rules() {
+        let sample_map = read_map(sample_input());
+        let sample_obstacles = sorted(find_looping_obstacles(&sample_map));
+        assert_eq!(sample_obstacles.len(), 6);
+        for edge in [EdgeRule::Leave, EdgeRule::Stop] {
+            let looping = find_looping_obstacles_with_rules(&sample_map, &MovementRules { edge, ..MovementRules::default() });
+            assert_eq!(sorted(looping), sample_obstacles);
+        }
+    }
 }
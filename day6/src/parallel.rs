@@ -0,0 +1,77 @@
+use std::{sync::{atomic::{AtomicUsize, Ordering}, Mutex}, thread, time::{Duration, Instant}};
+
+use crate::{collect_obstruction_candidates, is_loop, jump_table::JumpTable, Map, ObstacleOverlay, Position};
+
+// Tries every candidate obstruction on a pool of scoped worker threads.
+//
+// The workers share a jump table of the map's obstacles and each candidate
+// is laid over it with an `ObstacleOverlay`, so nothing is cloned per
+// candidate. The candidates are handed out one at a time through an atomic
+// counter, as some of them send the guard on much longer routes than
+// others.
+
+pub fn find_looping_obstructions(map: &Map, candidates: &[Position], threads: usize) -> Vec<Position> {
+    let guard = map.guard_start().expect("Expected exactly one guard");
+    let map_size = (map.width, map.height);
+    let table = JumpTable::new(map);
+    let next_candidate = AtomicUsize::new(0);
+    let looping_obstructions = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                let mut found = Vec::new();
+                while let Some(candidate) = candidates.get(next_candidate.fetch_add(1, Ordering::Relaxed)) {
+                    let overlay = ObstacleOverlay { base: &table, extra: candidate.clone() };
+                    if is_loop(guard.clone(), &overlay, map_size) {
+                        found.push(candidate.clone());
+                    }
+                }
+                looping_obstructions.lock().unwrap().extend(found);
+            });
+        }
+    });
+
+    // Sorted so that the result doesn't depend on how the work was split
+    let mut looping_obstructions = looping_obstructions.into_inner().unwrap();
+    looping_obstructions.sort_unstable_by_key(|position| (position.y, position.x));
+    looping_obstructions
+}
+
+pub fn count_looping_obstructions(map: &Map, threads: usize) -> usize {
+    find_looping_obstructions(map, &collect_obstruction_candidates(map), threads).len()
+}
+
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+// Average wall-clock time of `runs` calls, after one warm-up call
+pub fn benchmark<T>(runs: u32, mut solve: impl FnMut() -> T) -> (T, Duration) {
+    let result = solve();
+    let start = Instant::now();
+    for _ in 0..runs {
+        solve();
+    }
+    (result, start.elapsed() / runs.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{input, read_map, sample_input};
+
+    // The overlay search finds the sample's six looping obstructions on any
+    // number of threads, and agrees with cloning the map per candidate
+    #[test]
+    fn any_number_of_threads() {
+        let map = read_map(sample_input());
+        let candidates = collect_obstruction_candidates(&map);
+        let obstructions = find_looping_obstructions(&map, &candidates, 1);
+        assert_eq!(obstructions.len(), 6);
+        assert_eq!(obstructions[0], Position { x: 3, y: 6 });
+        assert_eq!(find_looping_obstructions(&map, &candidates, 4), obstructions);
+        assert_eq!(find_looping_obstructions(&map, &candidates, 64), obstructions);
+        assert_eq!(count_looping_obstructions(&read_map(input()), available_threads()), 1523);
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashSet;
+
+use crate::{get_positions_between, jump_table::JumpTable, route, Guard, Map, Position};
+
+// Searches for sets of extra obstructions, up to a budget, that trap the
+// guard or make it visit as many or as few cells as possible.
+//
+// An obstruction the guard never walks into doesn't change its route, so
+// only the cells on the route with the obstructions placed so far are
+// tried next. Each set of obstructions is tried once, in the order the
+// guard walks into them: the guard walks the same cells as before up to the
+// last obstruction placed, so the next one goes on a cell it reaches later.
+// When minimising, nothing placed past as many cells as the best placement
+// visits can do better, as the guard still walks all the cells before it.
+// The obstructions are patched into a single jump table on the way down and
+// reverted on the way back up.
+//
+// Trapping and maximising still try every placement along the route, about
+// L^budget of them for a route of L cells, so budgets beyond 2 are only
+// practical on maps the size of the sample.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Objective {
+    Trap,
+    MaximiseVisited,
+    MinimiseVisited,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Loop,
+    Visited(usize),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Placement {
+    // Sorted by row, then column
+    pub obstructions: Vec<Position>,
+    pub outcome: Outcome,
+}
+
+struct Search<'a> {
+    map: &'a Map,
+    guard: Guard,
+    table: JumpTable,
+    objective: Objective,
+    // Cells that are neither obstacles nor the guard's start
+    free_cells: usize,
+    best: Vec<Placement>,
+}
+
+// The cells the guard visits, in the order it first walks into them, or
+// nothing if it loops
+fn walk(guard: &Guard, table: &JumpTable, map_size: (usize, usize)) -> Option<Vec<Position>> {
+    let mut waypoints = HashSet::new();
+    let mut previous_position = guard.position.clone();
+    let mut cells = vec![guard.position.clone()];
+    let mut seen = vec![false; map_size.0 * map_size.1];
+    seen[guard.position.y * map_size.0 + guard.position.x] = true;
+    let mut is_loop = false;
+
+    route(guard.clone(), table, map_size, |waypoint| {
+        let mut segment = get_positions_between(&previous_position, &waypoint.position);
+        if waypoint.position.x < previous_position.x || waypoint.position.y < previous_position.y {
+            segment.reverse();
+        }
+        for position in segment {
+            let seen = &mut seen[position.y * map_size.0 + position.x];
+            if !*seen {
+                *seen = true;
+                cells.push(position);
+            }
+        }
+        previous_position = waypoint.position.clone();
+        let is_new_waypoint = waypoints.insert(waypoint);
+        is_loop |= !is_new_waypoint;
+        is_new_waypoint
+    });
+
+    (!is_loop).then_some(cells)
+}
+
+impl Search<'_> {
+    fn consider(&mut self, placement: Placement) {
+        let score = |placement: &Placement| match (placement.outcome, self.objective) {
+            (Outcome::Visited(visited), Objective::MaximiseVisited) => Some(visited as isize),
+            (Outcome::Visited(visited), Objective::MinimiseVisited) => Some(-(visited as isize)),
+            (Outcome::Loop, Objective::Trap) => Some(0),
+            _ => None,
+        };
+
+        let Some(new_score) = score(&placement) else { return };
+        match self.best.first().and_then(score) {
+            Some(best_score) if best_score > new_score => {},
+            Some(best_score) if best_score == new_score => self.best.push(placement),
+            _ => self.best = vec![placement],
+        }
+    }
+
+    // Whether placing another obstruction on the cell the guard first walks
+    // into after `walked` others can't lead to anything as good as the best
+    // placement so far
+    fn is_hopeless(&self, walked: usize, placed: usize) -> bool {
+        match (self.best.first().map(|placement| placement.outcome), self.objective) {
+            (Some(Outcome::Visited(visited)), Objective::MinimiseVisited) => walked > visited,
+            (Some(Outcome::Visited(visited)), Objective::MaximiseVisited) => self.free_cells - placed < visited,
+            _ => false,
+        }
+    }
+
+    // `first` is the index on the route of the cell the last obstruction
+    // was placed on
+    fn explore(&mut self, obstructions: &mut Vec<Position>, budget: usize, first: usize) {
+        let map_size = (self.map.width, self.map.height);
+        let mut placed = obstructions.clone();
+        placed.sort_unstable_by_key(|position| (position.y, position.x));
+
+        let Some(cells) = walk(&self.guard, &self.table, map_size) else {
+            // Any further obstruction would only make for a bigger trap
+            self.consider(Placement { obstructions: placed, outcome: Outcome::Loop });
+            return;
+        };
+        self.consider(Placement { obstructions: placed, outcome: Outcome::Visited(cells.len()) });
+
+        if obstructions.len() == budget {
+            return;
+        }
+
+        // The guard's start can't be obstructed
+        for (index, cell) in cells.into_iter().enumerate().skip(first.max(1)) {
+            if self.is_hopeless(index, obstructions.len()) {
+                break;
+            }
+            let patch = self.table.add(&cell);
+            obstructions.push(cell);
+            self.explore(obstructions, budget, index);
+            obstructions.pop();
+            self.table.revert(patch);
+        }
+    }
+}
+
+// Every best placement of at most `budget` obstructions. For trapping the
+// guard, these are the placements that trap it without any of their
+// obstructions being redundant.
+pub fn optimise(map: &Map, budget: usize, objective: Objective) -> Vec<Placement> {
+    let mut search = Search {
+        map,
+        guard: map.guard_start().expect("Expected exactly one guard"),
+        table: JumpTable::new(map),
+        objective,
+        free_cells: map.width * map.height - map.obstacles.count() - 1,
+        best: Vec::new(),
+    };
+    search.explore(&mut Vec::new(), budget, 0);
+
+    let mut best = search.best;
+    if objective == Objective::Trap {
+        let traps: Vec<Vec<Position>> = best.iter().map(|placement| placement.obstructions.clone()).collect();
+        best.retain(|placement| !traps.iter().any(|trap| trap.len() < placement.obstructions.len() && trap.iter().all(|position| placement.obstructions.contains(position))));
+    }
+    best.sort_unstable_by_key(|placement| placement.obstructions.iter().map(|position| (position.y, position.x)).collect::<Vec<_>>());
+    best
+}
+
+// The single obstruction that makes the guard visit the most cells before
+// leaving the map, if any obstruction lengthens its patrol at all
+pub fn find_most_lengthening_obstruction(map: &Map) -> Option<(Position, usize)> {
+    let best = optimise(map, 1, Objective::MaximiseVisited);
+
+    // Placing nothing is only among the best if nothing beats it
+    if best.iter().any(|placement| placement.obstructions.is_empty()) {
+        return None;
+    }
+
+    best.into_iter().find_map(|placement| match (placement.obstructions.first(), placement.outcome) {
+        (Some(position), Outcome::Visited(visited)) => Some((position.clone(), visited)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{input, read_map, sample_input, simulator::{collect_visited_positions_with_rules, TraceEnd}, rules::MovementRules};
+
+    // How many cells the guard visits with extra obstructions placed on a
+    // copy of the map, or nothing if it loops
+    fn visited_with(map: &Map, obstructions: &[&Position]) -> Option<usize> {
+        let mut map_copy = map.clone();
+        obstructions.iter().for_each(|position| map_copy.obstacles.add(position));
+        let (visited, end) = collect_visited_positions_with_rules(&map_copy, MovementRules::default());
+        (end == TraceEnd::LeftMap).then_some(visited.len())
+    }
+
+    fn free_cells(map: &Map) -> Vec<Position> {
+        let guard = map.guard_start().unwrap();
+        (0..map.height)
+            .flat_map(|y| (0..map.width).map(move |x| Position { x, y }))
+            .filter(|position| *position != guard.position && !map.obstacles.contains(position))
+            .collect()
+    }
+
+    // Optimising a single obstruction agrees with trying every cell of a
+    // copy of the map
+    #[test]
+    fn single_obstructions() {
+        let map = read_map(sample_input());
+        let single_obstructions: Vec<(Position, Option<usize>)> = free_cells(&map).into_iter()
+            .map(|position| {
+                let visited = visited_with(&map, &[&position]);
+                (position, visited)
+            })
+            .collect();
+
+        let traps = optimise(&map, 1, Objective::Trap);
+        let looping: Vec<Position> = single_obstructions.iter().filter(|(_, visited)| visited.is_none()).map(|(position, _)| position.clone()).collect();
+        assert_eq!(traps.iter().map(|trap| trap.obstructions[0].clone()).collect::<Vec<_>>(), looping);
+        let longest = single_obstructions.iter().filter_map(|(_, visited)| *visited).max().unwrap();
+        let shortest = single_obstructions.iter().filter_map(|(_, visited)| *visited).min().unwrap();
+        for (objective, expected) in [(Objective::MaximiseVisited, longest), (Objective::MinimiseVisited, shortest)] {
+            let best = optimise(&map, 1, objective);
+            assert!(best.iter().all(|placement| placement.outcome == Outcome::Visited(expected)));
+        }
+        // Nothing lengthens the sample's patrol, while an obstruction right in
+        // front of the guard sends it straight off the right edge
+        assert_eq!(longest, 41);
+        assert_eq!(find_most_lengthening_obstruction(&map), None);
+        assert_eq!(optimise(&map, 1, Objective::MinimiseVisited)[0].obstructions, vec![Position { x: 4, y: 5 }]);
+    }
+
+    // Ties with a patrol that already covers every free cell are kept: the
+    // guard visits both free cells with or without (0, 0) obstructed
+    #[test]
+    fn ties_with_the_bound() {
+        let tied = optimise(&read_map(".#\n^.\n"), 1, Objective::MaximiseVisited);
+        assert_eq!(tied, vec![
+            Placement { obstructions: Vec::new(), outcome: Outcome::Visited(2) },
+            Placement { obstructions: vec![Position { x: 0, y: 0 }], outcome: Outcome::Visited(2) },
+        ]);
+    }
+
+    // With two obstructions, the traps are the single ones plus every pair
+    // of cells that traps the guard only together, trying every pair of a
+    // copy of the map. The same pairs give the fewest cells the guard can
+    // be made to visit
+    #[test]
+    fn pairs_of_obstructions() {
+        let map = read_map(sample_input());
+        let free_cells = free_cells(&map);
+        let looping: Vec<&Position> = free_cells.iter().filter(|position| visited_with(&map, &[position]).is_none()).collect();
+        let mut expected_traps: Vec<Vec<Position>> = looping.iter().map(|&position| vec![position.clone()]).collect();
+        let mut shortest = usize::MAX;
+        for (index, first) in free_cells.iter().enumerate() {
+            for second in &free_cells[index + 1..] {
+                let is_new_trap = !looping.contains(&first) && !looping.contains(&second);
+                match visited_with(&map, &[first, second]) {
+                    None if is_new_trap => expected_traps.push(vec![first.clone(), second.clone()]),
+                    Some(visited) => shortest = shortest.min(visited),
+                    None => {},
+                }
+            }
+        }
+        expected_traps.sort_unstable_by_key(|trap| trap.iter().map(|position| (position.y, position.x)).collect::<Vec<_>>());
+
+        let pair_traps = optimise(&map, 2, Objective::Trap);
+        assert_eq!(pair_traps.iter().map(|trap| trap.obstructions.clone()).collect::<Vec<_>>(), expected_traps);
+        assert!(pair_traps.iter().all(|trap| trap.outcome == Outcome::Loop));
+        assert!(optimise(&map, 2, Objective::MinimiseVisited).iter().all(|placement| placement.outcome == Outcome::Visited(shortest)));
+    }
+
+    #[test]
+    fn most_lengthening_obstruction() {
+        assert_eq!(find_most_lengthening_obstruction(&read_map(input())), Some((Position { x: 102, y: 1 }, 5305)));
+    }
+}
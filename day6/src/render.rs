@@ -0,0 +1,117 @@
+use std::{io::{self, Write}, thread, time::Duration};
+
+use crate::{get_char_from_guard_facing, Facing, Guard, Map, Position};
+
+// Draws the map like the illustrations in the puzzle text: obstacles as
+// '#', an added obstruction as 'O', the guard by its facing, and visited
+// cells as '|' or '-' depending on the direction they were crossed in, or
+// '+' where the guard moved both ways (e.g. where it turned).
+
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+#[derive(Clone, Copy, Default)]
+struct Crossings {
+    vertical: bool,
+    horizontal: bool,
+}
+
+pub struct Canvas<'a> {
+    map: &'a Map,
+    obstruction: Option<&'a Position>,
+    crossings: Vec<Crossings>,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(map: &'a Map, obstruction: Option<&'a Position>) -> Canvas<'a> {
+        Canvas { map, obstruction, crossings: vec![Crossings::default(); map.width * map.height] }
+    }
+
+    pub fn mark(&mut self, guard: &Guard) {
+        let crossings = &mut self.crossings[guard.position.y * self.map.width + guard.position.x];
+        match guard.facing {
+            Facing::Up | Facing::Down => crossings.vertical = true,
+            Facing::Left | Facing::Right => crossings.horizontal = true,
+        }
+    }
+
+    pub fn draw(&self, guard: Option<&Guard>) -> String {
+        let mut output = String::with_capacity((self.map.width + 1) * self.map.height);
+
+        for y in 0..self.map.height {
+            for x in 0..self.map.width {
+                let position = Position { x, y };
+                let crossings = self.crossings[y * self.map.width + x];
+                output.push(match guard {
+                    Some(guard) if guard.position == position => get_char_from_guard_facing(&guard.facing),
+                    _ if self.obstruction == Some(&position) => 'O',
+                    _ if self.map.obstacles.contains(&position) => '#',
+                    _ => match (crossings.vertical, crossings.horizontal) {
+                        (true, true) => '+',
+                        (true, false) => '|',
+                        (false, true) => '-',
+                        (false, false) => '.',
+                    },
+                });
+            }
+            output.push('\n');
+        }
+
+        output
+    }
+}
+
+pub fn render(map: &Map, obstruction: Option<&Position>, trail: &[Guard], guard: Option<&Guard>) -> String {
+    let mut canvas = Canvas::new(map, obstruction);
+    trail.iter().for_each(|state| canvas.mark(state));
+    canvas.draw(guard)
+}
+
+// Redraws the whole map for every step of the trail, waiting `frame_delay`
+// between frames
+pub fn animate(map: &Map, obstruction: Option<&Position>, trail: &[Guard], frame_delay: Duration, output: &mut impl Write) -> io::Result<()> {
+    let mut canvas = Canvas::new(map, obstruction);
+
+    for guard in trail {
+        canvas.mark(guard);
+        write!(output, "{CLEAR_SCREEN}{}", canvas.draw(Some(guard)))?;
+        output.flush()?;
+        thread::sleep(frame_delay);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{looping_sample_map, simulator::record_trace};
+
+    const LOOPING_SAMPLE_ILLUSTRATION: &str = "\
+....#.....
+....+---+#
+....|...|.
+..#.|...|.
+....|..#|.
+....|...|.
+.#.O^---+.
+........#.
+#.........
+......#...
+";
+
+    // The first looping obstruction of the sample, as illustrated in the
+    // puzzle text
+    #[test]
+    fn looping_sample() {
+        let map = looping_sample_map();
+        let obstruction = Position { x: 3, y: 6 };
+        let trace = record_trace(map.guard_start().unwrap(), &map.obstacles, (map.width, map.height));
+        assert_eq!(render(&map, Some(&obstruction), &trace.states, Some(&trace.states[0])), LOOPING_SAMPLE_ILLUSTRATION);
+
+        let mut frames = Vec::new();
+        animate(&map, Some(&obstruction), &trace.states, Duration::ZERO, &mut frames).unwrap();
+        let frames = String::from_utf8(frames).unwrap();
+        assert_eq!(frames.matches(CLEAR_SCREEN).count(), trace.states.len());
+        assert!(frames.ends_with(&render(&map, Some(&obstruction), &trace.states, trace.states.last())));
+    }
+}
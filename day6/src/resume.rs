@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+
+use crate::{is_loop, jump_table::JumpTable, simulator::{get_position_ahead, Simulator, StepEvent}, Guard, Map, ObstacleOverlay, Position};
+
+// Finds the looping obstructions in a single walk along the original route.
+//
+// Up to the first time the guard would step onto a candidate, its route is
+// the same as without the candidate, so each candidate is only simulated
+// from the state right in front of it. A candidate the guard already
+// crossed earlier would have changed that earlier part of the route, so
+// it's only tried at the first time the guard reaches it.
+
+pub fn find_looping_obstructions(map: &Map) -> Vec<Position> {
+    search(map, |guard, overlay, map_size| is_loop(guard, overlay, map_size).then_some(()))
+        .into_iter()
+        .map(|(obstruction, _)| obstruction)
+        .collect()
+}
+
+// Runs `test` for every candidate, from the guard's state in front of it,
+// and keeps the candidates it returns something for
+pub fn search<T, F>(map: &Map, mut test: F) -> Vec<(Position, T)>
+where F: FnMut(Guard, &ObstacleOverlay<JumpTable>, (usize, usize)) -> Option<T> {
+    let guard = map.guard_start().expect("Expected exactly one guard");
+    let map_size = (map.width, map.height);
+    let table = JumpTable::new(map);
+
+    let mut simulator = Simulator::new(guard.clone(), &map.obstacles, map_size);
+    let mut visited = HashSet::from([guard.position]);
+    let mut found = Vec::new();
+
+    loop {
+        let guard = simulator.guard();
+        if let Some(ahead) = get_position_ahead(&guard, map_size) {
+            if !map.obstacles.contains(&ahead) && visited.insert(ahead.clone()) {
+                let overlay = ObstacleOverlay { base: &table, extra: ahead.clone() };
+                if let Some(result) = test(guard, &overlay, map_size) {
+                    found.push((ahead, result));
+                }
+            }
+        }
+
+        if simulator.step() == StepEvent::LeftMap {
+            return found;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{find_looping_obstacles, input, read_map, sample_input, sorted};
+
+    // Resuming in front of each candidate finds the same obstructions as
+    // restarting from the guard's start with a copy of the map
+    #[test]
+    fn agrees_with_restarting() {
+        for map in [read_map(sample_input()), read_map(input())] {
+            assert_eq!(sorted(find_looping_obstructions(&map)), sorted(find_looping_obstacles(&map)));
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{Facing, Position};
+
+// Variations on how the guard patrols, for exploring other versions of the
+// puzzle. The default rules are the puzzle's own: turn right, move in four
+// directions and leave the map at its edges.
+//
+// Headings are counted in eighths of a turn clockwise from up, so that the
+// same simulator works for four and eight directions of movement.
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Heading(u8);
+
+impl From<Facing> for Heading {
+    fn from(facing: Facing) -> Heading {
+        match facing {
+            Facing::Up => Heading(0),
+            Facing::Right => Heading(2),
+            Facing::Down => Heading(4),
+            Facing::Left => Heading(6),
+        }
+    }
+}
+
+impl Heading {
+    // Only headings along the rows and columns have a facing
+    pub fn facing(self) -> Option<Facing> {
+        match self.0 {
+            0 => Some(Facing::Up),
+            2 => Some(Facing::Right),
+            4 => Some(Facing::Down),
+            6 => Some(Facing::Left),
+            _ => None,
+        }
+    }
+
+    fn offset(self) -> (isize, isize) {
+        match self.0 {
+            0 => (0, -1),
+            1 => (1, -1),
+            2 => (1, 0),
+            3 => (1, 1),
+            4 => (0, 1),
+            5 => (-1, 1),
+            6 => (-1, 0),
+            _ => (-1, -1),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TurnRule {
+    Right,
+    Left,
+    Reverse,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Directions {
+    Four,
+    Eight,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EdgeRule {
+    Leave,
+    // Walking off one edge comes back on at the opposite one
+    Wrap,
+    // The guard stays on the last cell before the edge and ends its patrol
+    // there, instead of leaving the map
+    Stop,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MovementRules {
+    pub turn: TurnRule,
+    pub directions: Directions,
+    pub edge: EdgeRule,
+}
+
+impl Default for MovementRules {
+    fn default() -> MovementRules {
+        MovementRules { turn: TurnRule::Right, directions: Directions::Four, edge: EdgeRule::Leave }
+    }
+}
+
+impl MovementRules {
+    pub fn turn(&self, heading: Heading) -> Heading {
+        let step = match self.directions {
+            Directions::Four => 2,
+            Directions::Eight => 1,
+        };
+        let eighths = match self.turn {
+            TurnRule::Right => step,
+            TurnRule::Left => 8 - step,
+            TurnRule::Reverse => 4,
+        };
+        Heading((heading.0 + eighths) % 8)
+    }
+
+    // Whether the guard only ever walks straight along rows and columns
+    // until it hits an obstacle or reaches the edge, so that it can jump
+    // from one turn to the next
+    pub fn walks_in_segments(&self) -> bool {
+        self.directions == Directions::Four && self.edge != EdgeRule::Wrap
+    }
+
+    // The cell the guard walks into next, unless it reaches the edge
+    pub fn position_ahead(&self, position: &Position, heading: Heading, map_size: (usize, usize)) -> Option<Position> {
+        let (dx, dy) = heading.offset();
+        let x = position.x.checked_add_signed(dx).filter(|&x| x < map_size.0);
+        let y = position.y.checked_add_signed(dy).filter(|&y| y < map_size.1);
+
+        match (x, y, self.edge) {
+            (Some(x), Some(y), _) => Some(Position { x, y }),
+            (_, _, EdgeRule::Wrap) => Some(Position {
+                x: (position.x as isize + dx).rem_euclid(map_size.0 as isize) as usize,
+                y: (position.y as isize + dy).rem_euclid(map_size.1 as isize) as usize,
+            }),
+            (_, _, EdgeRule::Leave | EdgeRule::Stop) => None,
+        }
+    }
+}
+
+// Routing jumps from turn to turn, which only works for rules that walk in
+// segments
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RouteError {
+    NotInSegments,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect_visited_positions, input, looping_sample_map, read_map, sample_input, simulator::{collect_visited_positions_with_rules, TraceEnd}};
+
+    // The puzzle's rules are the default movement rules
+    #[test]
+    fn puzzle_rules() {
+        let sample_map = read_map(sample_input());
+        let puzzle_rules = MovementRules::default();
+        assert_eq!(collect_visited_positions_with_rules(&sample_map, puzzle_rules), (collect_visited_positions(&sample_map), TraceEnd::LeftMap));
+        assert_eq!(collect_visited_positions_with_rules(&read_map(input()), puzzle_rules).0.len(), 5145);
+        assert_eq!(collect_visited_positions_with_rules(&looping_sample_map(), puzzle_rules).1, TraceEnd::Loop);
+    }
+
+    #[test]
+    fn variants() {
+        let sample_map = read_map(sample_input());
+        let variant = |turn, directions, edge| collect_visited_positions_with_rules(&sample_map, MovementRules { turn, directions, edge });
+
+        // Turning left at (4, 1) leads straight off the left edge, and turning
+        // around leads back down and off the bottom edge
+        assert_eq!(variant(TurnRule::Left, Directions::Four, EdgeRule::Leave).0.len(), 10);
+        assert_eq!(variant(TurnRule::Reverse, Directions::Four, EdgeRule::Leave).0.len(), 9);
+        assert_eq!(variant(TurnRule::Right, Directions::Four, EdgeRule::Wrap).1, TraceEnd::Loop);
+        assert_eq!(variant(TurnRule::Left, Directions::Eight, EdgeRule::Wrap).1, TraceEnd::Loop);
+        // Turning an eighth at (4, 1) leads diagonally to (5, 0) and off the top
+        let (diagonal, end) = variant(TurnRule::Right, Directions::Eight, EdgeRule::Leave);
+        assert_eq!((diagonal.len(), end), (7, TraceEnd::LeftMap));
+        assert!(diagonal.contains(&Position { x: 5, y: 0 }));
+        // Stopping at the edge walks the same cells as leaving, but ends apart
+        assert_eq!(variant(TurnRule::Right, Directions::Four, EdgeRule::Stop), (collect_visited_positions(&sample_map), TraceEnd::Stopped));
+    }
+}
@@ -0,0 +1,416 @@
+use std::{collections::HashSet, fmt};
+
+use crate::{find_position_before_next_obstacle, get_char_from_guard_facing, get_guard_facing_from_char, rules::{EdgeRule, Heading, MovementRules}, Facing, Guard, Map, Obstacles, Position};
+
+// Simulates the guard one cell (or one turn) at a time, as opposed to
+// `route`, which jumps straight from one turn to the next. The guard moves
+// by the puzzle's rules unless given other movement rules.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StepEvent {
+    Moved,
+    Turned,
+    LeftMap,
+    // Reached the edge under `EdgeRule::Stop`
+    Stopped,
+}
+
+pub struct Simulator<'a> {
+    obstacles: &'a Obstacles,
+    map_size: (usize, usize),
+    rules: MovementRules,
+    position: Position,
+    heading: Heading,
+    // How the patrol ended, once it has
+    end: Option<StepEvent>,
+}
+
+// The cell in front of the guard, unless that's off the map
+pub fn get_position_ahead(guard: &Guard, map_size: (usize, usize)) -> Option<Position> {
+    let Position { x, y } = guard.position;
+    let (x, y) = match guard.facing {
+        Facing::Up => (Some(x), y.checked_sub(1)),
+        Facing::Down => (Some(x), y.checked_add(1).filter(|&y| y < map_size.1)),
+        Facing::Left => (x.checked_sub(1), Some(y)),
+        Facing::Right => (x.checked_add(1).filter(|&x| x < map_size.0), Some(y)),
+    };
+    Some(Position { x: x?, y: y? })
+}
+
+impl<'a> Simulator<'a> {
+    pub fn new(guard: Guard, obstacles: &'a Obstacles, map_size: (usize, usize)) -> Simulator<'a> {
+        Simulator::with_rules(guard, obstacles, map_size, MovementRules::default())
+    }
+
+    pub fn with_rules(guard: Guard, obstacles: &'a Obstacles, map_size: (usize, usize), rules: MovementRules) -> Simulator<'a> {
+        Simulator { obstacles, map_size, rules, position: guard.position, heading: Heading::from(guard.facing), end: None }
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    pub fn heading(&self) -> Heading {
+        self.heading
+    }
+
+    // Panics when the guard is heading diagonally, which only happens with
+    // eight directions of movement
+    pub fn guard(&self) -> Guard {
+        let facing = self.heading.facing().expect("The guard is heading diagonally");
+        Guard { position: self.position.clone(), facing }
+    }
+
+    pub fn step(&mut self) -> StepEvent {
+        if let Some(end) = self.end {
+            return end;
+        }
+
+        match self.rules.position_ahead(&self.position, self.heading, self.map_size) {
+            None => self.reach_edge(),
+            Some(position) if self.obstacles.contains(&position) => {
+                self.heading = self.rules.turn(self.heading);
+                StepEvent::Turned
+            },
+            Some(position) => {
+                self.position = position;
+                StepEvent::Moved
+            },
+        }
+    }
+
+    // Walks straight up to the next obstacle and turns, or up to the edge.
+    // On a wrapping map the guard may never hit anything, so it gives up
+    // walking straight after crossing as many cells as the map has
+    pub fn step_segment(&mut self) -> StepEvent {
+        if let Some(end) = self.end {
+            return end;
+        }
+
+        if !self.rules.walks_in_segments() {
+            let mut event = self.step();
+            for _ in 1..self.map_size.0 * self.map_size.1 {
+                if event != StepEvent::Moved {
+                    break;
+                }
+                event = self.step();
+            }
+            return event;
+        }
+
+        let (next_position, is_facing_obstacle) = find_position_before_next_obstacle(&self.guard(), self.obstacles, self.map_size);
+        self.position = next_position;
+
+        if is_facing_obstacle {
+            self.heading = self.rules.turn(self.heading);
+            StepEvent::Turned
+        } else {
+            self.reach_edge()
+        }
+    }
+
+    fn reach_edge(&mut self) -> StepEvent {
+        let end = match self.rules.edge {
+            EdgeRule::Stop => StepEvent::Stopped,
+            _ => StepEvent::LeftMap,
+        };
+        self.end = Some(end);
+        end
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TraceEnd {
+    LeftMap,
+    Stopped,
+    Loop,
+}
+
+impl StepEvent {
+    // How the patrol ended, if this step ended it
+    pub fn trace_end(self) -> Option<TraceEnd> {
+        match self {
+            StepEvent::LeftMap => Some(TraceEnd::LeftMap),
+            StepEvent::Stopped => Some(TraceEnd::Stopped),
+            StepEvent::Moved | StepEvent::Turned => None,
+        }
+    }
+}
+
+// Every state of the guard from the start, one per step. A turn shows up as
+// two consecutive states on the same position.
+#[derive(PartialEq, Eq, Debug)]
+pub struct Trace {
+    pub states: Vec<Guard>,
+    pub end: TraceEnd,
+}
+
+pub fn record_trace(guard: Guard, obstacles: &Obstacles, map_size: (usize, usize)) -> Trace {
+    let mut simulator = Simulator::new(guard.clone(), obstacles, map_size);
+    let mut states = vec![guard.clone()];
+    let mut seen_states = HashSet::from([guard]);
+
+    loop {
+        if let Some(end) = simulator.step().trace_end() {
+            return Trace { states, end };
+        }
+        if !seen_states.insert(simulator.guard()) {
+            return Trace { states, end: TraceEnd::Loop };
+        }
+        states.push(simulator.guard());
+    }
+}
+
+// Walks the guard cell by cell under any movement rules, calling back with
+// every cell it stands on, until its patrol ends or goes round in circles
+pub fn walk_with_rules<F>(guard: Guard, obstacles: &Obstacles, map_size: (usize, usize), rules: MovementRules, mut callback: F) -> TraceEnd
+where F: FnMut(&Position) {
+    let mut simulator = Simulator::with_rules(guard, obstacles, map_size, rules);
+    let mut seen_states = HashSet::from([(simulator.position().clone(), simulator.heading())]);
+    callback(simulator.position());
+
+    loop {
+        if let Some(end) = simulator.step().trace_end() {
+            return end;
+        }
+        if !seen_states.insert((simulator.position().clone(), simulator.heading())) {
+            return TraceEnd::Loop;
+        }
+        callback(simulator.position());
+    }
+}
+
+// The cells the guard visits under any movement rules, and how its patrol
+// ends
+pub fn collect_visited_positions_with_rules(map: &Map, rules: MovementRules) -> (HashSet<Position>, TraceEnd) {
+    let guard = map.guard_start().expect("Expected exactly one guard");
+    let mut visited_positions = HashSet::new();
+    let end = walk_with_rules(guard, &map.obstacles, (map.width, map.height), rules, |position| {
+        visited_positions.insert(position.clone());
+    });
+    (visited_positions, end)
+}
+
+// One "x,y,facing,steps" line per straight run: the state the run starts
+// in and how many cells the guard then moves without turning, followed by
+// how the trace ends, e.g.
+//
+//   4,6,^,5
+//   4,1,>,4
+//   ...
+//   left
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut states = self.states.iter().peekable();
+        while let Some(start) = states.next() {
+            let mut steps = 0;
+            let mut last = start.clone();
+            while let Some(next) = states.next_if(|next| next.facing == last.facing && Some(&next.position) == get_position_ahead(&last, UNBOUNDED).as_ref()) {
+                last = next.clone();
+                steps += 1;
+            }
+            writeln!(f, "{},{},{},{}", start.position.x, start.position.y, get_char_from_guard_facing(&start.facing), steps)?;
+        }
+        writeln!(f, "{}", match self.end {
+            TraceEnd::LeftMap => "left",
+            TraceEnd::Stopped => "stopped",
+            TraceEnd::Loop => "loop",
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum ReplayError {
+    // Starting at 1
+    InvalidLine(usize),
+    // Starting at 1, the line whose run takes the trace past `MAX_TRACE_STATES`
+    TooLong(usize),
+    Empty,
+    // The guard would have ended up somewhere else after this step
+    Diverged { step: usize, expected: Guard },
+    WrongEnd { expected: TraceEnd },
+}
+
+// Traces are read without their map, so runs may go anywhere but past the
+// top and left edges
+const UNBOUNDED: (usize, usize) = (usize::MAX, usize::MAX);
+
+// Nothing bounds runs to the right or down, so traces are capped at four
+// states per cell of a 512 by 512 map, well beyond the puzzle's
+const MAX_TRACE_STATES: usize = 4 * 512 * 512;
+
+// The state a run starts in and how many cells it then moves
+fn parse_run(line: &str) -> Option<(Guard, usize)> {
+    let mut fields = line.split(',');
+    let x = fields.next()?.parse().ok()?;
+    let y = fields.next()?.parse().ok()?;
+    let facing = match fields.next()? {
+        field @ ("^" | ">" | "v" | "<") => get_guard_facing_from_char(field.chars().next()?),
+        _ => return None,
+    };
+    let steps: usize = fields.next()?.parse().ok()?;
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some((Guard { position: Position { x, y }, facing }, steps))
+}
+
+impl Trace {
+    pub fn parse(text: &str) -> Result<Trace, ReplayError> {
+        let lines: Vec<&str> = text.lines().map(str::trim).collect();
+        let (end_line, state_lines) = lines.split_last().ok_or(ReplayError::Empty)?;
+
+        let end = match *end_line {
+            "left" => TraceEnd::LeftMap,
+            "stopped" => TraceEnd::Stopped,
+            "loop" => TraceEnd::Loop,
+            _ => return Err(ReplayError::InvalidLine(lines.len())),
+        };
+        let mut states = Vec::new();
+        for (line_index, line) in state_lines.iter().enumerate() {
+            let (mut guard, steps) = parse_run(line).ok_or(ReplayError::InvalidLine(line_index + 1))?;
+            if steps >= MAX_TRACE_STATES - states.len() {
+                return Err(ReplayError::TooLong(line_index + 1));
+            }
+            states.push(guard.clone());
+            for _ in 0..steps {
+                guard.position = get_position_ahead(&guard, UNBOUNDED).ok_or(ReplayError::InvalidLine(line_index + 1))?;
+                states.push(guard.clone());
+            }
+        }
+
+        if states.is_empty() {
+            return Err(ReplayError::Empty);
+        }
+        Ok(Trace { states, end })
+    }
+
+    // Checks that the map makes the guard take exactly the recorded steps,
+    // starting from the first recorded state
+    pub fn replay(&self, map: &Map) -> Result<(), ReplayError> {
+        let start = self.states.first().ok_or(ReplayError::Empty)?;
+        let expected = record_trace(start.clone(), &map.obstacles, (map.width, map.height));
+
+        if let Some(step) = (1..self.states.len()).find(|&step| expected.states.get(step) != Some(&self.states[step])) {
+            return match expected.states.get(step) {
+                Some(guard) => Err(ReplayError::Diverged { step, expected: guard.clone() }),
+                None => Err(ReplayError::WrongEnd { expected: expected.end }),
+            };
+        }
+        if expected.states.len() > self.states.len() {
+            return Err(ReplayError::Diverged { step: self.states.len(), expected: expected.states[self.states.len()].clone() });
+        }
+        if expected.end != self.end {
+            return Err(ReplayError::WrongEnd { expected: expected.end });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{input, looping_sample_map, read_map, route, route_with_rules, rules::{EdgeRule, TurnRule}, sample_input};
+
+    fn count_traced_positions(trace: &Trace) -> usize {
+        trace.states.iter().map(|guard| &guard.position).collect::<HashSet<_>>().len()
+    }
+
+    fn sample_trace() -> (Map, Trace) {
+        let map = read_map(sample_input());
+        let trace = record_trace(map.guard_start().unwrap(), &map.obstacles, (map.width, map.height));
+        (map, trace)
+    }
+
+    // Walking cell by cell visits the same cells as jumping between turns
+    #[test]
+    fn traces_visit_every_cell() {
+        let (_, trace) = sample_trace();
+        assert_eq!(trace.end, TraceEnd::LeftMap);
+        assert_eq!(count_traced_positions(&trace), 41);
+        let map = read_map(input());
+        assert_eq!(count_traced_positions(&record_trace(map.guard_start().unwrap(), &map.obstacles, (map.width, map.height))), 5145);
+    }
+
+    #[test]
+    fn segments_end_on_waypoints() {
+        let map = read_map(sample_input());
+        let map_size = (map.width, map.height);
+        let guard = map.guard_start().unwrap();
+
+        let mut waypoints = Vec::new();
+        route(guard.clone(), &map.obstacles, map_size, |waypoint| {
+            waypoints.push(waypoint.position);
+            true
+        });
+        let mut simulator = Simulator::new(guard.clone(), &map.obstacles, map_size);
+        let mut segment_ends = vec![simulator.position().clone()];
+        while simulator.step_segment() == StepEvent::Turned {
+            segment_ends.push(simulator.position().clone());
+        }
+        segment_ends.push(simulator.position().clone());
+        assert_eq!(segment_ends[1..], waypoints);
+
+        // Under other turns and edges too
+        for (turn, edge, end) in [(TurnRule::Left, EdgeRule::Leave, StepEvent::LeftMap), (TurnRule::Reverse, EdgeRule::Leave, StepEvent::LeftMap), (TurnRule::Right, EdgeRule::Stop, StepEvent::Stopped)] {
+            let rules = MovementRules { turn, edge, ..MovementRules::default() };
+            let mut waypoints = Vec::new();
+            let routed = route_with_rules(guard.clone(), &map.obstacles, map_size, &rules, |waypoint| {
+                waypoints.push(waypoint.position);
+                true
+            });
+            assert_eq!(routed, Ok(()));
+            let mut simulator = Simulator::with_rules(guard.clone(), &map.obstacles, map_size, rules);
+            let mut segment_ends = Vec::new();
+            while simulator.step_segment() == StepEvent::Turned {
+                segment_ends.push(simulator.position().clone());
+            }
+            segment_ends.push(simulator.position().clone());
+            assert_eq!(segment_ends, waypoints);
+            assert_eq!((simulator.step(), simulator.step_segment()), (end, end));
+        }
+    }
+
+    // Traces round-trip through their text format and replay on their map
+    #[test]
+    fn traces_round_trip() {
+        let (map, trace) = sample_trace();
+        let trace_text = trace.to_string();
+        assert!(trace_text.starts_with("4,6,^,5\n4,1,>,4\n8,1,v,5\n"));
+        assert!(trace_text.ends_with("1,7,>,6\n7,7,v,2\nleft\n"));
+        assert_eq!(trace_text.lines().count(), 12);
+        assert_eq!(Trace::parse(&trace_text), Ok(Trace { states: trace.states.clone(), end: TraceEnd::LeftMap }));
+        assert_eq!(trace.replay(&map), Ok(()));
+        // Turning a cell early
+        let tampered = Trace::parse(&trace_text.replacen("4,6,^,5", "4,6,^,4\n4,2,>,0", 1)).unwrap();
+        assert_eq!(tampered.replay(&map), Err(ReplayError::Diverged { step: 5, expected: Guard { position: Position { x: 4, y: 1 }, facing: Facing::Up } }));
+
+        let stopped_trace = Trace { states: trace.states, end: TraceEnd::Stopped };
+        assert!(stopped_trace.to_string().ends_with("7,7,v,2\nstopped\n"));
+        assert_eq!(Trace::parse(&stopped_trace.to_string()), Ok(stopped_trace));
+    }
+
+    #[test]
+    fn malformed_traces() {
+        assert_eq!(Trace::parse("4,6,^,1\n4,5\nleft\n"), Err(ReplayError::InvalidLine(2)));
+        assert_eq!(Trace::parse("4,6,^,0\n"), Err(ReplayError::InvalidLine(1)));
+        assert_eq!(Trace::parse("4,6,^garbage,0\nleft\n"), Err(ReplayError::InvalidLine(1)));
+        assert_eq!(Trace::parse("4,6,^\nleft\n"), Err(ReplayError::InvalidLine(1)));
+        assert_eq!(Trace::parse("4,6,^,0\n0,1,^,2\nleft\n"), Err(ReplayError::InvalidLine(2)));
+        // Runs nothing on the way bounds are refused before they're walked
+        assert_eq!(Trace::parse("0,0,v,99999999999\nleft\n"), Err(ReplayError::TooLong(1)));
+        assert_eq!(Trace::parse(&format!("4,6,^,5\n0,0,>,{}\nleft\n", MAX_TRACE_STATES - 6)), Err(ReplayError::TooLong(2)));
+    }
+
+    #[test]
+    fn looping_traces_replay() {
+        let (sample_map, _) = sample_trace();
+        let looping_map = looping_sample_map();
+        let looping_trace = record_trace(looping_map.guard_start().unwrap(), &looping_map.obstacles, (looping_map.width, looping_map.height));
+        assert_eq!(looping_trace.end, TraceEnd::Loop);
+        assert_eq!(looping_trace.replay(&looping_map), Ok(()));
+        assert_eq!(Trace::parse(&looping_trace.to_string().replace("loop", "left")).unwrap().replay(&looping_map), Err(ReplayError::WrongEnd { expected: TraceEnd::Loop }));
+        assert_eq!(looping_trace.replay(&sample_map), Err(ReplayError::Diverged { step: 22, expected: Guard { position: Position { x: 3, y: 6 }, facing: Facing::Left } }));
+    }
+}